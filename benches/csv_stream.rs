@@ -0,0 +1,80 @@
+//! Streams generated CSV files of growing size through `rcli csv --stream -f ndjson`
+//! and reports peak resident memory, which should stay flat across sizes.
+//!
+//! Run with `cargo bench --bench csv_stream`, optionally `ROWS=10000000` for a
+//! multi-GB input.
+
+use std::{
+    env, fs,
+    io::{BufWriter, Write},
+    path::Path,
+    time::Instant,
+};
+
+use anyhow::Result;
+use rcli::{process_csv, CsvConvertOpts, CsvQueryOpts, CsvReaderOpts, OutputFormat};
+
+fn generate(path: &Path, rows: usize) -> Result<()> {
+    let mut writer = BufWriter::new(fs::File::create(path)?);
+    writeln!(writer, "Name,Position,DOB,Nationality,Kit Number,Active")?;
+    for i in 0..rows {
+        writeln!(
+            writer,
+            "Player {i},Midfielder,{}-{:02}-{:02},Italy,{},{}",
+            1980 + i % 30,
+            1 + i % 12,
+            1 + i % 28,
+            i % 99,
+            i % 2 == 0
+        )?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+// peak resident set size of this process in kB, Linux only
+fn peak_rss_kb() -> Option<u64> {
+    let status = fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|l| l.starts_with("VmHWM:"))?;
+    line.split_whitespace().nth(1)?.parse().ok()
+}
+
+fn main() -> Result<()> {
+    let max_rows = env::var("ROWS")
+        .ok()
+        .and_then(|r| r.parse().ok())
+        .unwrap_or(1_000_000);
+    let dir = env::temp_dir().join("rcli-csv-stream");
+    fs::create_dir_all(&dir)?;
+
+    let opts = CsvConvertOpts {
+        stream: true,
+        ..Default::default()
+    };
+    let mut rows = max_rows / 100;
+    while rows <= max_rows {
+        let input = dir.join(format!("{rows}.csv"));
+        let output = dir.join(format!("{rows}.ndjson"));
+        generate(&input, rows)?;
+        let size = fs::metadata(&input)?.len();
+
+        let start = Instant::now();
+        process_csv(
+            &input.to_string_lossy(),
+            BufWriter::new(fs::File::create(&output)?),
+            OutputFormat::Ndjson,
+            &CsvReaderOpts::default(),
+            &opts,
+            &CsvQueryOpts::default(),
+        )?;
+        println!(
+            "{rows:>10} rows {:>8} kB input: {:>8.2?}, peak rss {} kB",
+            size / 1024,
+            start.elapsed(),
+            peak_rss_kb().map_or("n/a".to_string(), |kb| kb.to_string())
+        );
+        rows *= 10;
+    }
+    fs::remove_dir_all(&dir)?;
+    Ok(())
+}
@@ -6,7 +6,7 @@ use enum_dispatch::enum_dispatch;
 
 use crate::{process_decode, process_encode, CmdExecutor};
 
-use super::verify_file;
+use super::{verify_file, OutputOpts};
 
 #[derive(Debug, Subcommand)]
 #[enum_dispatch(CmdExecutor)]
@@ -24,20 +24,34 @@ pub struct Base64EncodeOpts {
 
     #[arg(short, long, value_parser=parse_base64_format, default_value = "standard")]
     pub format: Base64Format,
+
+    /// Break lines after this many characters, 64 for PEM or 76 (the
+    /// default when given without a value) for MIME
+    #[arg(long, num_args = 0..=1, default_missing_value = "76", value_parser = clap::value_parser!(u16).range(1..))]
+    pub wrap: Option<u16>,
+
+    #[command(flatten)]
+    pub output: OutputOpts,
 }
 
 impl CmdExecutor for Base64EncodeOpts {
     async fn execute(self) -> Result<()> {
-        process_encode(&self.input, self.format)?;
-        Ok(())
+        let mut output = self.output.writer("-")?;
+        process_encode(
+            &self.input,
+            &mut output,
+            self.format,
+            self.wrap.map(usize::from),
+        )?;
+        output.commit()
     }
 }
 
 impl CmdExecutor for Base64DecodeOpts {
     async fn execute(self) -> Result<()> {
-        let decoded = process_decode(&self.input, self.format)?;
-        println!("{:?}", String::from_utf8(decoded));
-        Ok(())
+        let mut output = self.output.writer("-")?;
+        process_decode(&self.input, &mut output, self.format, self.hexdump)?;
+        output.commit()
     }
 }
 
@@ -48,6 +62,13 @@ pub struct Base64DecodeOpts {
 
     #[arg(short, long, value_parser=parse_base64_format, default_value = "standard")]
     pub format: Base64Format,
+
+    /// Preview the decoded bytes as a hex dump instead of writing them raw
+    #[arg(long)]
+    pub hexdump: bool,
+
+    #[command(flatten)]
+    pub output: OutputOpts,
 }
 
 #[derive(Debug, Clone)]
@@ -0,0 +1,48 @@
+use clap::Parser;
+
+use crate::{process_convert, CmdExecutor};
+
+use super::{csv::parse_format, verify_file, CsvReaderOpts, OutputFormat, OutputOpts};
+
+#[derive(Debug, Parser)]
+pub struct ConvertOpts {
+    #[arg(short, long, value_parser=verify_file, default_value = "-")]
+    pub input: String,
+
+    #[command(flatten)]
+    pub output: OutputOpts,
+
+    /// Input format, detected from the file extension or content when omitted
+    #[arg(long, value_parser=parse_format)]
+    pub from: Option<OutputFormat>,
+
+    #[arg(short, long, default_value = "json", value_parser=parse_format)]
+    pub to: OutputFormat,
+
+    #[command(flatten)]
+    pub csv: CsvReaderOpts,
+
+    /// Keep every CSV cell as a string
+    #[arg(long)]
+    pub no_infer: bool,
+
+    /// Key holding the array of records in TOML documents
+    #[arg(long, default_value = "records")]
+    pub toml_root: String,
+}
+
+impl CmdExecutor for ConvertOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let mut output = self.output.writer(&format!("output.{}", self.to))?;
+        process_convert(
+            &self.input,
+            &mut output,
+            self.from,
+            self.to,
+            &self.csv,
+            !self.no_infer,
+            &self.toml_root,
+        )?;
+        output.commit()
+    }
+}
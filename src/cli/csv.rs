@@ -1,51 +1,611 @@
-use std::{fmt::Display, str::FromStr};
+use std::{
+    fmt::Display,
+    io::{self, IsTerminal},
+    str::FromStr,
+};
 
 use anyhow::{anyhow, Error};
-use clap::Parser;
+use clap::{ArgAction, Args, Parser, Subcommand};
+use encoding_rs::Encoding;
+use enum_dispatch::enum_dispatch;
 
-use crate::{process_csv, CmdExecutor};
+use crate::{
+    format_diff, format_stats_table, get_writer, process_csv, process_csv_concat, process_csv_diff,
+    process_csv_join, process_csv_show, process_csv_stats, process_csv_validate, process_to_csv,
+    CmdExecutor, Output,
+};
 
 use super::verify_file;
 
 #[derive(Debug, Parser)]
+#[command(args_conflicts_with_subcommands = true)]
 pub struct CsvOpts {
-    #[arg(short, long, value_parser=verify_file)]
-    pub input: String,
+    #[command(subcommand)]
+    pub command: Option<CsvSubCommand>,
 
-    #[arg(short, long, default_value = "output.json")]
-    pub output: Option<String>,
+    #[arg(short, long, value_parser=verify_file, default_value = "-")]
+    pub input: String,
 
-    #[arg(long, default_value_t = true)]
-    pub header: bool,
+    #[command(flatten)]
+    pub output: OutputOpts,
 
-    #[arg(short, long, default_value_t = ',')]
-    pub delimiter: char,
+    #[command(flatten)]
+    pub reader: CsvReaderOpts,
 
     #[arg(short, long, default_value="json", value_parser=parse_format)]
     pub format: OutputFormat,
+
+    #[command(flatten)]
+    pub convert: CsvConvertOpts,
+
+    #[command(flatten)]
+    pub query: CsvQueryOpts,
+
+    /// Convert from this format (json, ndjson, yaml, toml) back to CSV instead
+    #[arg(
+        long,
+        value_parser=parse_format,
+        conflicts_with_all = [
+            "format", "schema", "stream", "unflatten",
+            "select", "filter", "distinct", "sort_by", "limit",
+        ]
+    )]
+    pub from: Option<OutputFormat>,
 }
 
-impl CmdExecutor for CsvOpts {
+#[derive(Debug, Subcommand)]
+#[enum_dispatch(CmdExecutor)]
+pub enum CsvSubCommand {
+    #[command(name = "show", about = "Show a CSV file as a table")]
+    Show(CsvShowOpts),
+    #[command(name = "stats", about = "Profile every column of a CSV file")]
+    Stats(CsvStatsOpts),
+    #[command(name = "validate", about = "Check a CSV file against a schema")]
+    Validate(CsvValidateOpts),
+    #[command(
+        name = "join",
+        about = "Join two CSV files on key columns, writing to stdout unless -o is given"
+    )]
+    Join(CsvJoinOpts),
+    #[command(
+        name = "concat",
+        about = "Append CSV files, filling missing columns, writing to stdout unless -o is given"
+    )]
+    Concat(CsvConcatOpts),
+    #[command(name = "diff", about = "Compare two versions of a CSV file by key")]
+    Diff(CsvDiffOpts),
+}
+
+#[derive(Debug, Parser)]
+pub struct CsvShowOpts {
+    #[arg(short, long, value_parser=verify_file, default_value = "-")]
+    pub input: String,
+
+    #[command(flatten)]
+    pub reader: CsvReaderOpts,
+
+    /// Show only the first N rows
+    #[arg(long, conflicts_with_all = ["tail", "range"])]
+    pub head: Option<usize>,
+
+    /// Show only the last N rows
+    #[arg(long, conflicts_with = "range")]
+    pub tail: Option<usize>,
+
+    /// Show rows START:END, 1-based and inclusive; either bound may be omitted
+    #[arg(long, value_parser=parse_range)]
+    pub range: Option<RowWindow>,
+
+    /// Cut cells wider than this many columns
+    #[arg(long, default_value_t = 40)]
+    pub max_width: usize,
+
+    /// Prefix every row with its row number
+    #[arg(short = 'n', long)]
+    pub row_numbers: bool,
+}
+
+impl CmdExecutor for CsvShowOpts {
     async fn execute(self) -> anyhow::Result<()> {
-        let output = if let Some(output) = self.output {
-            output
-        } else {
-            format!("output.{}", &self.format)
+        let window = match (self.head, self.tail, self.range) {
+            (Some(n), _, _) => RowWindow::Head(n),
+            (_, Some(n), _) => RowWindow::Tail(n),
+            (_, _, Some(range)) => range,
+            _ => RowWindow::All,
         };
+        let table = process_csv_show(
+            &self.input,
+            &self.reader,
+            window,
+            self.max_width,
+            self.row_numbers,
+        )?;
+        print!("{}", table);
+        Ok(())
+    }
+}
+
+/// Rows of a CSV file to display.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RowWindow {
+    All,
+    Head(usize),
+    Tail(usize),
+    Range(usize, usize),
+}
+
+impl FromStr for RowWindow {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (start, end) = s
+            .split_once(':')
+            .ok_or_else(|| anyhow!("Invalid range, expected START:END"))?;
+        let start = match start {
+            "" => 1,
+            n => n.parse()?,
+        };
+        let end = match end {
+            "" => usize::MAX,
+            n => n.parse()?,
+        };
+        if start == 0 || start > end {
+            return Err(anyhow!("Invalid range, expected 1 <= START <= END"));
+        }
+        Ok(RowWindow::Range(start, end))
+    }
+}
+
+fn parse_range(range: &str) -> Result<RowWindow, Error> {
+    range.parse()
+}
+
+#[derive(Debug, Parser)]
+pub struct CsvStatsOpts {
+    #[arg(short, long, value_parser=verify_file, default_value = "-")]
+    pub input: String,
+
+    #[command(flatten)]
+    pub reader: CsvReaderOpts,
+
+    #[arg(short, long, default_value = "table", value_parser=parse_stats_format)]
+    pub format: StatsFormat,
+
+    /// Number of most frequent values to report per column
+    #[arg(long, default_value_t = 5)]
+    pub top: usize,
+}
+
+impl CmdExecutor for CsvStatsOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let stats = process_csv_stats(&self.input, &self.reader, self.top)?;
+        match self.format {
+            StatsFormat::Table => print!("{}", format_stats_table(&stats)),
+            StatsFormat::Json => println!("{}", serde_json::to_string_pretty(&stats)?),
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum StatsFormat {
+    Table,
+    Json,
+}
+
+impl FromStr for StatsFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "table" => Ok(StatsFormat::Table),
+            "json" => Ok(StatsFormat::Json),
+            _ => Err(anyhow!("Invalid stats format")),
+        }
+    }
+}
+
+fn parse_stats_format(format: &str) -> Result<StatsFormat, Error> {
+    format.parse()
+}
+
+#[derive(Debug, Parser)]
+pub struct CsvValidateOpts {
+    #[arg(short, long, value_parser=verify_file, default_value = "-")]
+    pub input: String,
+
+    #[command(flatten)]
+    pub reader: CsvReaderOpts,
+
+    /// Column spec TOML, or a JSON Schema (`.json`) describing one row
+    #[arg(long, value_parser=verify_file)]
+    pub schema: String,
+}
+
+impl CmdExecutor for CsvValidateOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let violations = process_csv_validate(&self.input, &self.reader, &self.schema)?;
+        for v in &violations {
+            println!("line {}, column {:?}: {}", v.line, v.column, v.message);
+        }
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(anyhow!("{} violations found", violations.len()))
+        }
+    }
+}
+
+#[derive(Debug, Parser)]
+pub struct CsvJoinOpts {
+    #[arg(long, value_parser=verify_file)]
+    pub left: String,
+
+    #[arg(long, value_parser=verify_file)]
+    pub right: String,
+
+    /// Key columns, e.g. `id` or `first,last`
+    #[arg(long, value_delimiter = ',', required = true)]
+    pub on: Vec<String>,
+
+    /// Key columns of the right file when they are named differently
+    #[arg(long, value_delimiter = ',')]
+    pub right_on: Option<Vec<String>>,
+
+    /// inner, left, right or full
+    #[arg(long, default_value = "inner", value_parser=parse_join_kind)]
+    pub how: JoinKind,
+
+    #[command(flatten)]
+    pub output: OutputOpts,
+
+    #[command(flatten)]
+    pub reader: CsvReaderOpts,
+}
+
+impl CmdExecutor for CsvJoinOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let right_on = self.right_on.as_ref().unwrap_or(&self.on);
+        let mut output = self.output.writer("-")?;
+        process_csv_join(
+            &self.left,
+            &self.right,
+            &self.on,
+            right_on,
+            self.how,
+            &self.reader,
+            &mut output,
+        )?;
+        output.commit()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum JoinKind {
+    Inner,
+    Left,
+    Right,
+    Full,
+}
+
+impl FromStr for JoinKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "inner" => Ok(JoinKind::Inner),
+            "left" => Ok(JoinKind::Left),
+            "right" => Ok(JoinKind::Right),
+            "full" | "outer" => Ok(JoinKind::Full),
+            _ => Err(anyhow!("Invalid join kind")),
+        }
+    }
+}
+
+fn parse_join_kind(how: &str) -> Result<JoinKind, Error> {
+    how.parse()
+}
 
-        process_csv(&self.input, &output, self.format)?;
+#[derive(Debug, Parser)]
+pub struct CsvConcatOpts {
+    #[arg(value_parser=verify_file, required = true)]
+    pub inputs: Vec<String>,
+
+    #[command(flatten)]
+    pub output: OutputOpts,
+
+    #[command(flatten)]
+    pub reader: CsvReaderOpts,
+}
+
+impl CmdExecutor for CsvConcatOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let mut output = self.output.writer("-")?;
+        process_csv_concat(&self.inputs, &self.reader, &mut output)?;
+        output.commit()
+    }
+}
+
+#[derive(Debug, Parser)]
+pub struct CsvDiffOpts {
+    #[arg(value_parser=verify_file)]
+    pub old: String,
+
+    #[arg(value_parser=verify_file)]
+    pub new: String,
+
+    /// Columns identifying a row, e.g. `id` or `Name,DOB`
+    #[arg(long, value_delimiter = ',', required = true)]
+    pub key: Vec<String>,
+
+    /// text or json
+    #[arg(short, long, default_value = "text", value_parser=parse_diff_format)]
+    pub format: DiffFormat,
+
+    #[command(flatten)]
+    pub reader: CsvReaderOpts,
+}
+
+impl CmdExecutor for CsvDiffOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let diff = process_csv_diff(&self.old, &self.new, &self.key, &self.reader)?;
+        match self.format {
+            DiffFormat::Text => {
+                if !io::stdout().is_terminal() {
+                    colored::control::set_override(false);
+                }
+                print!("{}", format_diff(&diff, &self.key));
+            }
+            DiffFormat::Json => println!("{}", serde_json::to_string_pretty(&diff)?),
+        }
         Ok(())
     }
 }
 
+#[derive(Debug, Clone, Copy)]
+pub enum DiffFormat {
+    Text,
+    Json,
+}
+
+impl FromStr for DiffFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "text" => Ok(DiffFormat::Text),
+            "json" => Ok(DiffFormat::Json),
+            _ => Err(anyhow!("Invalid diff format")),
+        }
+    }
+}
+
+fn parse_diff_format(format: &str) -> Result<DiffFormat, Error> {
+    format.parse()
+}
+
+#[derive(Debug, Clone, Default, Args)]
+pub struct OutputOpts {
+    /// Output file, `-` for stdout
+    #[arg(short, long)]
+    pub output: Option<String>,
+
+    /// Replace an existing output file (the default)
+    #[arg(long, overrides_with = "no_clobber")]
+    pub overwrite: bool,
+
+    /// Fail instead of replacing an existing output file
+    #[arg(long, overrides_with = "overwrite")]
+    pub no_clobber: bool,
+}
+
+impl OutputOpts {
+    /// Open the output, or `default` when `-o` is not given.
+    pub fn writer(&self, default: &str) -> anyhow::Result<Output> {
+        get_writer(self.output.as_deref().unwrap_or(default), !self.no_clobber)
+    }
+}
+
+#[derive(Debug, Clone, Args)]
+pub struct CsvReaderOpts {
+    /// Whether the first row holds column names, `--header=false` for headerless files
+    #[arg(long, default_value_t = true, action = ArgAction::Set, num_args = 0..=1, default_missing_value = "true")]
+    pub header: bool,
+
+    /// Field delimiter, `\t` or `tab` for TSV
+    #[arg(short, long, default_value = ",", value_parser=parse_byte)]
+    pub delimiter: u8,
+
+    #[arg(long, default_value = "\"", value_parser=parse_byte)]
+    pub quote: u8,
+
+    /// Escape character for quotes, instead of doubling them
+    #[arg(long, value_parser=parse_byte)]
+    pub escape: Option<u8>,
+
+    /// Skip lines starting with this character
+    #[arg(long, value_parser=parse_byte)]
+    pub comment: Option<u8>,
+
+    /// Allow records with a varying number of fields
+    #[arg(long)]
+    pub flexible: bool,
+
+    /// Trim whitespace around headers and fields
+    #[arg(long)]
+    pub trim: bool,
+
+    /// Column names to use instead of the header row, e.g. `a,b,c`
+    #[arg(long, value_delimiter = ',')]
+    pub columns: Option<Vec<String>>,
+
+    /// Input encoding, e.g. `utf-16le` or `windows-1252`; taken from the BOM or
+    /// guessed from the content if omitted
+    #[arg(long, value_parser=parse_encoding)]
+    pub encoding: Option<&'static Encoding>,
+
+    /// Guess the delimiter, quote and header presence from the data
+    #[arg(long, conflicts_with_all = ["delimiter", "quote", "header"])]
+    pub sniff: bool,
+}
+
+impl Default for CsvReaderOpts {
+    fn default() -> Self {
+        Self {
+            header: true,
+            delimiter: b',',
+            quote: b'"',
+            escape: None,
+            comment: None,
+            flexible: false,
+            trim: false,
+            columns: None,
+            encoding: None,
+            sniff: false,
+        }
+    }
+}
+
+fn parse_encoding(label: &str) -> Result<&'static Encoding, Error> {
+    Encoding::for_label(label.as_bytes()).ok_or_else(|| anyhow!("Unknown encoding"))
+}
+
+#[derive(Debug, Clone, Args)]
+pub struct CsvConvertOpts {
+    /// Infer column types (integer, float, bool, null, date) from the data
+    #[arg(long = "infer-types", overrides_with = "no_infer")]
+    pub infer_types: bool,
+
+    /// Emit every cell as a string
+    #[arg(long = "no-infer", overrides_with = "infer_types")]
+    pub no_infer: bool,
+
+    /// TOML file pinning column types, e.g. `"Kit Number" = "integer"`
+    #[arg(long, value_parser=verify_file)]
+    pub schema: Option<String>,
+
+    /// Key holding the array of records in TOML output
+    #[arg(long, default_value = "records")]
+    pub toml_root: String,
+
+    /// Build nested objects and arrays from headers like `address.city` or `tags[0]`
+    #[arg(long)]
+    pub unflatten: bool,
+
+    /// Write records as they are read instead of buffering the whole file,
+    /// inferring types from the first `--sample` rows
+    #[arg(long)]
+    pub stream: bool,
+
+    /// Number of rows used to infer types when streaming
+    #[arg(long, default_value_t = 1000)]
+    pub sample: usize,
+}
+
+impl CsvConvertOpts {
+    pub fn infer(&self) -> bool {
+        !self.no_infer
+    }
+}
+
+impl Default for CsvConvertOpts {
+    fn default() -> Self {
+        Self {
+            infer_types: false,
+            no_infer: false,
+            schema: None,
+            toml_root: "records".to_string(),
+            unflatten: false,
+            stream: false,
+            sample: 1000,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Args)]
+pub struct CsvQueryOpts {
+    /// Columns to output, e.g. `Name,Position`
+    #[arg(long, value_delimiter = ',')]
+    pub select: Option<Vec<String>>,
+
+    /// Keep rows matching a predicate, e.g. `Kit Number > 10 and Name ~ '^G'`
+    #[arg(long = "where")]
+    pub filter: Option<String>,
+
+    /// Keep only the first row for each distinct value of these columns
+    #[arg(long, value_delimiter = ',')]
+    pub distinct: Option<Vec<String>>,
+
+    /// Sort rows by these columns
+    #[arg(long, value_delimiter = ',')]
+    pub sort_by: Option<Vec<String>>,
+
+    /// Sort in descending order
+    #[arg(long, requires = "sort_by")]
+    pub desc: bool,
+
+    /// Output at most this many rows
+    #[arg(long)]
+    pub limit: Option<usize>,
+}
+
+impl CmdExecutor for CsvOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        if let Some(command) = self.command {
+            return command.execute().await;
+        }
+        // without `-o` the output is named after its format, e.g. `output.json`
+        let format = self.from.map_or(self.format, |_| OutputFormat::Csv);
+        let mut output = self.output.writer(&format!("output.{}", format))?;
+        match self.from {
+            Some(from) => process_to_csv(
+                &self.input,
+                &mut output,
+                from,
+                &self.reader,
+                &self.convert.toml_root,
+            )?,
+            None => process_csv(
+                &self.input,
+                &mut output,
+                self.format,
+                &self.reader,
+                &self.convert,
+                &self.query,
+            )?,
+        }
+        output.commit()
+    }
+}
+
 #[derive(Debug, Copy, Clone)]
 pub enum OutputFormat {
     Json,
+    Ndjson,
     Toml,
     Yaml,
+    Csv,
 }
 
-fn parse_format(format: &str) -> Result<OutputFormat, Error> {
+fn parse_byte(s: &str) -> Result<u8, Error> {
+    let c = match s {
+        "\\t" | "tab" => '\t',
+        _ => {
+            let mut chars = s.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => c,
+                _ => return Err(anyhow!("Expected a single character")),
+            }
+        }
+    };
+    if c.is_ascii() {
+        Ok(c as u8)
+    } else {
+        Err(anyhow!("Expected an ASCII character"))
+    }
+}
+
+pub(super) fn parse_format(format: &str) -> Result<OutputFormat, Error> {
     // OutputFormat::from_str(format)
     format.parse::<OutputFormat>()
 }
@@ -56,8 +616,10 @@ impl FromStr for OutputFormat {
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         match s.to_lowercase().as_str() {
             "json" => Ok(OutputFormat::Json),
+            "ndjson" | "jsonl" => Ok(OutputFormat::Ndjson),
             "toml" => Ok(OutputFormat::Toml),
-            "yaml" => Ok(OutputFormat::Yaml),
+            "yaml" | "yml" => Ok(OutputFormat::Yaml),
+            "csv" => Ok(OutputFormat::Csv),
             _ => Err(anyhow!("Invalid format")),
         }
     }
@@ -67,8 +629,10 @@ impl From<OutputFormat> for &'static str {
     fn from(value: OutputFormat) -> Self {
         match value {
             OutputFormat::Json => "json",
+            OutputFormat::Ndjson => "ndjson",
             OutputFormat::Toml => "toml",
             OutputFormat::Yaml => "yaml",
+            OutputFormat::Csv => "csv",
         }
     }
 }
@@ -78,3 +642,46 @@ impl Display for OutputFormat {
         write!(f, "{}", Into::<&str>::into(*self))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_byte() {
+        assert_eq!(parse_byte(";").unwrap(), b';');
+        assert_eq!(parse_byte("\\t").unwrap(), b'\t');
+        assert_eq!(parse_byte("tab").unwrap(), b'\t');
+        assert!(parse_byte(";;").is_err());
+        assert!(parse_byte("§").is_err());
+    }
+
+    #[test]
+    fn test_header_flag() {
+        let opts = CsvOpts::parse_from(["csv", "-i", "-", "--header=false", "-d", "tab"]);
+        assert!(!opts.reader.header);
+        assert_eq!(opts.reader.delimiter, b'\t');
+        let opts = CsvOpts::parse_from(["csv", "-i", "-", "--header"]);
+        assert!(opts.reader.header);
+    }
+
+    #[test]
+    fn test_parse_range() {
+        assert_eq!(parse_range("3:5").unwrap(), RowWindow::Range(3, 5));
+        assert_eq!(parse_range(":5").unwrap(), RowWindow::Range(1, 5));
+        assert_eq!(parse_range("3:").unwrap(), RowWindow::Range(3, usize::MAX));
+        assert!(parse_range("0:5").is_err());
+        assert!(parse_range("5:3").is_err());
+        assert!(parse_range("5").is_err());
+    }
+
+    #[test]
+    fn test_csv_subcommand() {
+        let opts = CsvOpts::parse_from(["csv", "stats", "-i", "-", "-f", "json", "--top", "3"]);
+        match opts.command {
+            Some(CsvSubCommand::Stats(stats)) => assert_eq!(stats.top, 3),
+            _ => panic!("expected stats subcommand"),
+        }
+        assert!(CsvOpts::try_parse_from(["csv", "-i", "-", "stats"]).is_err());
+    }
+}
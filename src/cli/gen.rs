@@ -0,0 +1,141 @@
+use std::str::FromStr;
+
+use anyhow::{anyhow, Error};
+use clap::{Args, Parser, Subcommand};
+use enum_dispatch::enum_dispatch;
+
+use crate::{get_rng, process_token, process_uuid, CmdExecutor, SecretRng, TokenPolicy};
+
+#[derive(Debug, Subcommand)]
+#[enum_dispatch(CmdExecutor)]
+pub enum GenSubCommand {
+    #[command(
+        name = "token",
+        about = "Generate an API token with a checksum secret scanners can verify"
+    )]
+    Token(GenTokenOpts),
+    #[command(name = "uuid", about = "Generate a random or time-ordered UUID")]
+    Uuid(GenUuidOpts),
+}
+
+#[derive(Debug, Parser)]
+pub struct GenTokenOpts {
+    /// Number of random bytes
+    #[arg(long, default_value_t = 32, value_parser = clap::value_parser!(u16).range(1..))]
+    pub bytes: u16,
+
+    /// hex, base64url, base32 or base58
+    #[arg(short, long, default_value = "base64url", value_parser = parse_token_encoding)]
+    pub encoding: TokenEncoding,
+
+    /// Put in front of the token, e.g. `sk_live_`
+    #[arg(long, default_value = "")]
+    pub prefix: String,
+
+    /// Leave out the CRC32 checksum at the end
+    #[arg(long)]
+    pub no_checksum: bool,
+
+    /// Number of tokens to generate
+    #[arg(short, long, default_value_t = 1)]
+    pub count: usize,
+
+    #[command(flatten)]
+    pub seed: SeedOpts,
+}
+
+impl CmdExecutor for GenTokenOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let policy = TokenPolicy {
+            bytes: self.bytes.into(),
+            encoding: self.encoding,
+            prefix: self.prefix,
+            checksum: !self.no_checksum,
+        };
+        let mut rng = self.seed.rng();
+        for _ in 0..self.count {
+            println!("{}", process_token(&policy, &mut rng)?);
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Parser)]
+pub struct GenUuidOpts {
+    /// Random UUID (the default)
+    #[arg(long, conflicts_with = "v7")]
+    pub v4: bool,
+
+    /// Time-ordered UUID, sorting by creation time
+    #[arg(long)]
+    pub v7: bool,
+
+    /// Number of UUIDs to generate
+    #[arg(short, long, default_value_t = 1)]
+    pub count: usize,
+
+    #[command(flatten)]
+    pub seed: SeedOpts,
+}
+
+impl CmdExecutor for GenUuidOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let version = if self.v7 {
+            UuidVersion::V7
+        } else {
+            UuidVersion::V4
+        };
+        let mut rng = self.seed.rng();
+        for _ in 0..self.count {
+            println!("{}", process_uuid(version, &mut rng)?);
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Default, Args)]
+pub struct SeedOpts {
+    /// INSECURE: seed the generator for reproducible output in tests and
+    /// fixtures; anyone knowing the seed can recreate what it generates
+    #[arg(long)]
+    pub seed: Option<u64>,
+}
+
+impl SeedOpts {
+    /// The OS generator, or a seeded one when `--seed` is given.
+    pub fn rng(&self) -> SecretRng {
+        get_rng(self.seed)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TokenEncoding {
+    Hex,
+    Base64Url,
+    Base32,
+    Base58,
+}
+
+impl FromStr for TokenEncoding {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "hex" => Ok(TokenEncoding::Hex),
+            "base64url" => Ok(TokenEncoding::Base64Url),
+            "base32" => Ok(TokenEncoding::Base32),
+            "base58" => Ok(TokenEncoding::Base58),
+            _ => Err(anyhow!("Invalid token encoding")),
+        }
+    }
+}
+
+fn parse_token_encoding(encoding: &str) -> Result<TokenEncoding, Error> {
+    encoding.parse()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UuidVersion {
+    V4,
+    V7,
+}
@@ -1,32 +1,433 @@
-use anyhow::Ok;
-use clap::Parser;
+use std::{
+    io::{BufRead, BufReader},
+    str::FromStr,
+};
 
-use crate::{process_genpass, CmdExecutor};
+use anyhow::{anyhow, Ok};
+use clap::{ArgGroup, Parser, Subcommand};
+use enum_dispatch::enum_dispatch;
+
+use super::{verify_file, SeedOpts};
+use crate::{
+    format_password_check, get_reader, process_genpass, process_passphrase, process_password_check,
+    CmdExecutor, GeneratedPassword, PassphrasePolicy, PasswordPolicy, Pattern, WordCase, Wordlist,
+    SYMBOL,
+};
 
 #[derive(Debug, Parser)]
+#[command(
+    args_conflicts_with_subcommands = true,
+    group(ArgGroup::new("classes").multiple(true))
+)]
 pub struct GenpassOpts {
-    #[arg(long, default_value_t = 16)]
-    pub length: u8,
-    #[arg(long, default_value_t = true)]
+    #[command(subcommand)]
+    pub command: Option<GenpassSubCommand>,
+
+    /// Number of passwords to generate
+    #[arg(short, long, default_value_t = 1)]
+    pub count: usize,
+
+    /// plain (one password per line), json or csv; the latter two include the
+    /// score, crack time and entropy
+    #[arg(short, long, default_value = "plain", value_parser = parse_genpass_format)]
+    pub format: GenpassFormat,
+
+    /// Generate a diceware passphrase of words instead of a password
+    #[arg(long, conflicts_with_all = ["length", "classes", "exclude_ambiguous", "exclude_chars", "no_repeat", "no_sequential"])]
+    pub passphrase: bool,
+
+    /// Number of words in the passphrase
+    #[arg(long, default_value_t = 6, requires = "passphrase")]
+    pub words: usize,
+
+    /// Separator between passphrase words
+    #[arg(long, default_value = "-", requires = "passphrase")]
+    pub separator: String,
+
+    /// Case of passphrase words: lower, title or upper
+    #[arg(long, default_value = "lower", value_parser = parse_word_case, requires = "passphrase")]
+    pub case: WordCase,
+
+    /// Append a random digit to the passphrase
+    #[arg(long, requires = "passphrase")]
+    pub append_digit: bool,
+
+    /// Append a random symbol to the passphrase
+    #[arg(long, requires = "passphrase")]
+    pub append_symbol: bool,
+
+    /// Word list to use instead of the EFF large wordlist, one word per line
+    #[arg(long, value_parser = verify_file, requires = "passphrase")]
+    pub wordlist: Option<String>,
+
+    /// Generate from a template, e.g. `Cvccvc-99-Cvccvc` or `[A-Z]{4}-[0-9]{4}`:
+    /// c/C consonant, v/V vowel, a/A letter, 9 digit, s symbol, [...] set,
+    /// {4} repeats the previous slot, \ escapes a literal
+    #[arg(long, value_parser = parse_pattern, conflicts_with_all = ["passphrase", "length", "classes"])]
+    pub pattern: Option<Pattern>,
+
+    /// Alternate consonants and vowels for a password that can be read aloud
+    #[arg(long, conflicts_with_all = ["passphrase", "pattern", "classes"])]
+    pub pronounceable: bool,
+
+    #[arg(long, default_value_t = 16, value_parser = clap::value_parser!(u16).range(1..))]
+    pub length: u16,
+
+    /// Use uppercase letters (the default)
+    #[arg(long, overrides_with = "no_uppercase", group = "classes")]
     pub uppercase: bool,
-    #[arg(long, default_value_t = true)]
+    /// Leave out uppercase letters
+    #[arg(long, overrides_with = "uppercase", group = "classes")]
+    pub no_uppercase: bool,
+    /// Use lowercase letters (the default)
+    #[arg(long, overrides_with = "no_lowercase", group = "classes")]
     pub lowercase: bool,
-    #[arg(long, default_value_t = true)]
+    /// Leave out lowercase letters
+    #[arg(long, overrides_with = "lowercase", group = "classes")]
+    pub no_lowercase: bool,
+    /// Use digits (the default)
+    #[arg(long, overrides_with = "no_number", group = "classes")]
     pub number: bool,
-    #[arg(long, default_value_t = true)]
+    /// Leave out digits
+    #[arg(long, overrides_with = "number", group = "classes")]
+    pub no_number: bool,
+    /// Use symbols (the default)
+    #[arg(long, overrides_with = "no_symbol", group = "classes")]
     pub symbol: bool,
+    /// Leave out symbols
+    #[arg(long, overrides_with = "symbol", group = "classes")]
+    pub no_symbol: bool,
+
+    /// Minimum number of uppercase letters
+    #[arg(
+        long,
+        default_value_t = 1,
+        conflicts_with = "no_uppercase",
+        group = "classes"
+    )]
+    pub min_uppercase: usize,
+    /// Minimum number of lowercase letters
+    #[arg(
+        long,
+        default_value_t = 1,
+        conflicts_with = "no_lowercase",
+        group = "classes"
+    )]
+    pub min_lowercase: usize,
+    /// Minimum number of digits
+    #[arg(
+        long,
+        default_value_t = 1,
+        conflicts_with = "no_number",
+        group = "classes"
+    )]
+    pub min_number: usize,
+    /// Minimum number of symbols
+    #[arg(
+        long,
+        default_value_t = 1,
+        conflicts_with = "no_symbol",
+        group = "classes"
+    )]
+    pub min_symbol: usize,
+
+    /// Characters to draw symbols from
+    #[arg(long, default_value = SYMBOL)]
+    pub symbols: String,
+
+    /// Leave out look-alike characters: 0, O, 1, l and I
+    #[arg(long)]
+    pub exclude_ambiguous: bool,
+
+    /// Characters never to use, e.g. `"'\`
+    #[arg(long, default_value = "")]
+    pub exclude_chars: String,
+
+    /// Never put the same character twice in a row
+    #[arg(long)]
+    pub no_repeat: bool,
+
+    /// Avoid runs like `abc` or `321`
+    #[arg(long)]
+    pub no_sequential: bool,
+
+    /// Regenerate until the zxcvbn score (0-4) is at least this
+    #[arg(long, default_value_t = 0, value_parser = clap::value_parser!(u8).range(0..=4))]
+    pub min_score: u8,
+
+    #[command(flatten)]
+    pub seed: SeedOpts,
 }
 
 impl CmdExecutor for GenpassOpts {
     async fn execute(self) -> anyhow::Result<()> {
-        let password = process_genpass(
-            self.length,
-            self.uppercase,
-            self.lowercase,
-            self.number,
-            self.symbol,
-        )?;
-        println!("{}", password);
+        if let Some(command) = self.command {
+            return command.execute().await;
+        }
+        let mut rng = self.seed.rng();
+        let passwords = if self.passphrase {
+            let policy = PassphrasePolicy {
+                words: self.words,
+                separator: self.separator,
+                case: self.case,
+                digit: self.append_digit,
+                symbol: self.append_symbol,
+                symbols: self.symbols,
+                wordlist: match self.wordlist {
+                    Some(path) => Wordlist::load(path)?,
+                    None => Wordlist::default(),
+                },
+                min_score: self.min_score,
+            };
+            (0..self.count)
+                .map(|_| process_passphrase(&policy, &mut rng))
+                .collect::<anyhow::Result<Vec<_>>>()?
+        } else {
+            let class = |disabled: bool, min: usize| (!disabled).then_some(min);
+            let pattern = match self.pattern {
+                Some(pattern) => Some(pattern),
+                None if self.pronounceable => Some(Pattern::pronounceable(self.length.into())),
+                None => None,
+            };
+            let policy = PasswordPolicy {
+                pattern,
+                length: self.length.into(),
+                uppercase: class(self.no_uppercase, self.min_uppercase),
+                lowercase: class(self.no_lowercase, self.min_lowercase),
+                number: class(self.no_number, self.min_number),
+                symbol: class(self.no_symbol, self.min_symbol),
+                symbols: self.symbols,
+                exclude_ambiguous: self.exclude_ambiguous,
+                exclude_chars: self.exclude_chars,
+                no_repeat: self.no_repeat,
+                no_sequential: self.no_sequential,
+                min_score: self.min_score,
+            };
+            (0..self.count)
+                .map(|_| process_genpass(&policy, &mut rng))
+                .collect::<anyhow::Result<Vec<_>>>()?
+        };
+        print!("{}", format_passwords(&passwords, self.format)?);
         Ok(())
     }
 }
+
+#[derive(Debug, Subcommand)]
+#[enum_dispatch(CmdExecutor)]
+pub enum GenpassSubCommand {
+    #[command(
+        name = "check",
+        about = "Estimate the strength of passwords, one per line"
+    )]
+    Check(GenpassCheckOpts),
+}
+
+#[derive(Debug, Parser)]
+pub struct GenpassCheckOpts {
+    #[arg(short, long, value_parser = verify_file, default_value = "-")]
+    pub input: String,
+
+    /// Words an attacker may know, e.g. the username or email
+    #[arg(long, value_delimiter = ',')]
+    pub user_input: Vec<String>,
+
+    /// Exit with an error if any password scores lower (0-4)
+    #[arg(long, value_parser = clap::value_parser!(u8).range(0..=4))]
+    pub min_score: Option<u8>,
+}
+
+impl CmdExecutor for GenpassCheckOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let user_inputs = self
+            .user_input
+            .iter()
+            .map(String::as_str)
+            .collect::<Vec<_>>();
+        let (mut checked, mut failed) = (0, 0);
+        for (i, line) in BufReader::new(get_reader(&self.input)?).lines().enumerate() {
+            let line = line?;
+            let password = line.trim_end_matches('\r');
+            if password.is_empty() {
+                continue;
+            }
+            let check = process_password_check(password, &user_inputs)?;
+            print!(
+                "{}",
+                format_password_check(&format!("line {}", i + 1), &check)
+            );
+            checked += 1;
+            if self.min_score.is_some_and(|min| check.score < min) {
+                failed += 1;
+            }
+        }
+        match self.min_score {
+            Some(min) if failed > 0 => Err(anyhow!(
+                "{} of {} passwords scored below {}",
+                failed,
+                checked,
+                min
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Render passwords one per line (`plain`), or as records with their score,
+/// crack time and entropy (`json`, `csv`).
+fn format_passwords(
+    passwords: &[GeneratedPassword],
+    format: GenpassFormat,
+) -> anyhow::Result<String> {
+    Ok(match format {
+        GenpassFormat::Plain => passwords
+            .iter()
+            .map(|p| format!("{}\n", p.password))
+            .collect(),
+        GenpassFormat::Json => format!("{}\n", serde_json::to_string_pretty(passwords)?),
+        GenpassFormat::Csv => {
+            let mut writer = csv::Writer::from_writer(Vec::new());
+            for password in passwords {
+                writer.serialize(password)?;
+            }
+            String::from_utf8(writer.into_inner()?)?
+        }
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GenpassFormat {
+    Plain,
+    Json,
+    Csv,
+}
+
+impl FromStr for GenpassFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "plain" => Ok(GenpassFormat::Plain),
+            "json" => Ok(GenpassFormat::Json),
+            "csv" => Ok(GenpassFormat::Csv),
+            _ => Err(anyhow!("Invalid genpass format")),
+        }
+    }
+}
+
+fn parse_genpass_format(format: &str) -> Result<GenpassFormat, anyhow::Error> {
+    format.parse()
+}
+
+fn parse_pattern(pattern: &str) -> Result<Pattern, anyhow::Error> {
+    pattern.parse()
+}
+
+fn parse_word_case(case: &str) -> Result<WordCase, anyhow::Error> {
+    case.parse()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_negatable_class_flags() {
+        let opts = GenpassOpts::parse_from(["genpass", "--no-symbol", "--no-number", "--number"]);
+        assert!(opts.no_symbol);
+        assert!(!opts.no_number);
+        assert!(!opts.no_uppercase);
+        assert!(
+            GenpassOpts::try_parse_from(["genpass", "--no-symbol", "--min-symbol", "2"]).is_err()
+        );
+        assert!(GenpassOpts::try_parse_from(["genpass", "--length", "0"]).is_err());
+        assert_eq!(
+            GenpassOpts::parse_from(["genpass", "--length", "1000"]).length,
+            1000
+        );
+    }
+
+    #[test]
+    fn test_format_passwords() -> anyhow::Result<()> {
+        let policy = PasswordPolicy::default();
+        let mut rng = SeedOpts::default().rng();
+        let passwords = vec![
+            process_genpass(&policy, &mut rng)?,
+            process_genpass(&policy, &mut rng)?,
+        ];
+
+        let plain = format_passwords(&passwords, GenpassFormat::Plain)?;
+        assert_eq!(plain.lines().count(), 2);
+        assert_eq!(plain.lines().next(), Some(passwords[0].password.as_str()));
+
+        let json: serde_json::Value =
+            serde_json::from_str(&format_passwords(&passwords, GenpassFormat::Json)?)?;
+        assert_eq!(json[1]["password"], passwords[1].password);
+        assert!(json[1]["crack_time"].is_string());
+
+        let csv = format_passwords(&passwords, GenpassFormat::Csv)?;
+        assert!(csv.starts_with("password,score,crack_time,entropy\n"));
+        assert_eq!(csv.lines().count(), 3);
+        Ok(())
+    }
+
+    #[test]
+    fn test_check_subcommand() {
+        let opts = GenpassOpts::parse_from([
+            "genpass",
+            "check",
+            "--min-score",
+            "3",
+            "--user-input",
+            "juve,dybala",
+        ]);
+        match opts.command {
+            Some(GenpassSubCommand::Check(check)) => {
+                assert_eq!(check.min_score, Some(3));
+                assert_eq!(check.user_input, ["juve", "dybala"]);
+                assert_eq!(check.input, "-");
+            }
+            None => panic!("expected the check subcommand"),
+        }
+        assert!(GenpassOpts::parse_from(["genpass", "-c", "2"])
+            .command
+            .is_none());
+    }
+
+    #[test]
+    fn test_pattern_flags() {
+        let opts = GenpassOpts::parse_from(["genpass", "--pattern", "[A-Z]{4}-9{4}"]);
+        assert!(opts.pattern.is_some());
+        assert!(GenpassOpts::try_parse_from(["genpass", "--pattern", "x"]).is_err());
+        assert!(GenpassOpts::try_parse_from(["genpass", "--pattern", "9", "--no-symbol"]).is_err());
+        assert!(
+            GenpassOpts::try_parse_from(["genpass", "--pronounceable", "--min-number", "2"])
+                .is_err()
+        );
+        assert!(
+            GenpassOpts::try_parse_from(["genpass", "--pronounceable", "--length", "8"]).is_ok()
+        );
+    }
+
+    #[test]
+    fn test_passphrase_flags() {
+        let opts =
+            GenpassOpts::parse_from(["genpass", "--passphrase", "--words", "4", "--case", "title"]);
+        assert!(opts.passphrase);
+        assert_eq!(opts.words, 4);
+        assert_eq!(opts.case, WordCase::Title);
+        assert!(GenpassOpts::try_parse_from(["genpass", "--words", "4"]).is_err());
+        for flag in [
+            &["--length", "3"][..],
+            &["--no-symbol"],
+            &["--min-number", "2"],
+            &["--exclude-chars", "x"],
+            &["--no-repeat"],
+        ] {
+            let args = ["genpass", "--passphrase"].iter().chain(flag);
+            assert!(GenpassOpts::try_parse_from(args).is_err(), "{:?}", flag);
+        }
+        assert!(
+            GenpassOpts::try_parse_from(["genpass", "--passphrase", "--min-score", "4"]).is_ok()
+        );
+    }
+}
@@ -1,10 +1,12 @@
 mod base64;
+mod convert;
 mod csv;
+mod gen;
 mod genpass;
 mod http;
 mod text;
 
-pub use self::{base64::*, csv::*, genpass::*, http::*, text::*};
+pub use self::{base64::*, convert::*, csv::*, gen::*, genpass::*, http::*, text::*};
 use clap::{Parser, Subcommand};
 use enum_dispatch::enum_dispatch;
 use std::path::Path;
@@ -14,17 +16,32 @@ use std::path::Path;
 pub struct Opts {
     #[command(subcommand)]
     pub command: SubCommand,
+
+    /// Log what rcli detects along the way, e.g. the encoding of an input file
+    #[arg(short, long, global = true)]
+    pub verbose: bool,
 }
 
+// parsed once per run, so the size of the csv variant doesn't matter
+#[allow(clippy::large_enum_variant)]
 #[derive(Debug, Subcommand)]
 #[enum_dispatch(CmdExecutor)]
 pub enum SubCommand {
-    #[command(name = "csv", about = "Show SCV or convert CSV to other format")]
+    #[command(name = "csv", about = "Show, profile or convert CSV files")]
     Csv(CsvOpts),
 
+    #[command(
+        name = "convert",
+        about = "Convert between JSON, YAML, TOML, NDJSON and CSV, writing output.<to> unless -o is given"
+    )]
+    Convert(ConvertOpts),
+
     #[command(name = "genpass", about = "Generate a random password")]
     Genpass(GenpassOpts),
 
+    #[command(subcommand, about = "Generate API tokens and UUIDs")]
+    Gen(GenSubCommand),
+
     #[command(subcommand, about = "encode & decode with base64")]
     Base64(Base64SubCommand),
 
@@ -10,7 +10,7 @@ use enum_dispatch::enum_dispatch;
 
 use crate::{process_generate, process_sign, process_verify, CmdExecutor};
 
-use super::verify_file;
+use super::{verify_file, SeedOpts};
 
 #[derive(Debug, Subcommand)]
 #[enum_dispatch(CmdExecutor)]
@@ -76,11 +76,14 @@ pub struct GenerateKeyOpts {
 
     #[arg(short, long, default_value = "", value_parser=parse_path)]
     pub output: PathBuf,
+
+    #[command(flatten)]
+    pub seed: SeedOpts,
 }
 
 impl CmdExecutor for GenerateKeyOpts {
     async fn execute(self) -> Result<()> {
-        let key = process_generate(&self.format)?;
+        let key = process_generate(&self.format, &mut self.seed.rng())?;
         match self.format {
             TextSignFormat::Blake3 => {
                 let name = self.output.join("blake3.txt");
@@ -1,19 +1,27 @@
 mod process;
 use enum_dispatch::enum_dispatch;
 pub use process::{
-    process_csv, process_decode, process_encode, process_generate, process_genpass,
-    process_http_serve, process_sign, process_verify,
+    format_diff, format_password_check, format_stats_table, process_convert, process_csv,
+    process_csv_concat, process_csv_diff, process_csv_join, process_csv_show, process_csv_stats,
+    process_csv_validate, process_decode, process_encode, process_generate, process_genpass,
+    process_http_serve, process_passphrase, process_password_check, process_sign, process_to_csv,
+    process_token, process_uuid, process_verify, random_bytes, verify_token, GeneratedPassword,
+    PassphrasePolicy, PasswordPolicy, Pattern, TokenPolicy, WordCase, Wordlist, SYMBOL,
 };
 
 mod cli;
 pub use cli::{
-    Base64DecodeOpts, Base64EncodeOpts, Base64SubCommand, CsvOpts, GenerateKeyOpts, GenpassOpts,
-    HttpServeOpts, HttpSubCommand, Opts, OutputFormat, SubCommand, TextSignFormat, TextSignOpts,
-    TextSubCommand, TextVerifyOpts,
+    Base64DecodeOpts, Base64EncodeOpts, Base64SubCommand, ConvertOpts, CsvConcatOpts,
+    CsvConvertOpts, CsvDiffOpts, CsvJoinOpts, CsvOpts, CsvQueryOpts, CsvReaderOpts, CsvShowOpts,
+    CsvStatsOpts, CsvSubCommand, CsvValidateOpts, DiffFormat, GenSubCommand, GenTokenOpts,
+    GenUuidOpts, GenerateKeyOpts, GenpassCheckOpts, GenpassFormat, GenpassOpts, GenpassSubCommand,
+    HttpServeOpts, HttpSubCommand, JoinKind, Opts, OutputFormat, OutputOpts, RowWindow, SeedOpts,
+    StatsFormat, SubCommand, TextSignFormat, TextSignOpts, TextSubCommand, TextVerifyOpts,
+    TokenEncoding, UuidVersion,
 };
 
 mod utils;
-pub use utils::get_reader;
+pub use utils::{get_reader, get_rng, get_writer, Output, SecretRng};
 
 #[enum_dispatch]
 #[allow(async_fn_in_trait)]
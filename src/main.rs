@@ -1,10 +1,23 @@
 use clap::Parser;
 use rcli::{CmdExecutor, Opts};
+use tracing::level_filters::LevelFilter;
+use tracing_subscriber::EnvFilter;
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    tracing_subscriber::fmt::init();
     let opts = Opts::parse();
+    let filter = if opts.verbose {
+        EnvFilter::new("info")
+    } else {
+        // warnings, e.g. about an insecure --seed, show unless RUST_LOG says otherwise
+        EnvFilter::builder()
+            .with_default_directive(LevelFilter::WARN.into())
+            .from_env_lossy()
+    };
+    tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(std::io::stderr)
+        .init();
     opts.command.execute().await?;
 
     Ok(())
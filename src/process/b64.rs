@@ -1,52 +1,269 @@
-use base64::{engine::general_purpose, Engine};
-use std::io::Read;
+use base64::{
+    engine::{general_purpose, GeneralPurpose},
+    read::DecoderReader,
+    write::EncoderWriter,
+};
+use std::io::{self, Read, Write};
 
 use crate::{cli::Base64Format, get_reader};
 
-pub fn process_encode(input: &str, format: Base64Format) -> anyhow::Result<String> {
-    let mut reader: Box<dyn Read> = get_reader(input)?;
+/// Stream `input` to `output` base64 encoded, breaking lines after `wrap`
+/// characters if given, e.g. 64 for PEM or 76 for MIME.
+pub fn process_encode(
+    input: &str,
+    output: impl Write,
+    format: Base64Format,
+    wrap: Option<usize>,
+) -> anyhow::Result<()> {
+    let mut reader = get_reader(input)?;
+    let mut output = LineWrap {
+        inner: output,
+        width: wrap.unwrap_or(usize::MAX),
+        column: 0,
+    };
 
-    let mut buf = Vec::new();
-    reader.read_to_end(&mut buf)?;
+    let mut encoder = EncoderWriter::new(&mut output, engine(&format));
+    io::copy(&mut reader, &mut encoder)?;
+    encoder.finish()?;
+    drop(encoder);
 
-    let encoded = match format {
-        Base64Format::Standard => general_purpose::STANDARD.encode(&buf),
-        Base64Format::Urlsafe => general_purpose::URL_SAFE_NO_PAD.encode(&buf),
-    };
+    if output.column > 0 {
+        output.inner.write_all(b"\n")?;
+    }
+    output.inner.flush()?;
+    Ok(())
+}
 
-    println!("{}", encoded);
-    Ok(encoded)
+/// Stream base64 from `input` to `output` as raw bytes, skipping line breaks
+/// and other whitespace, or with `hexdump` as a `hexdump -C` style preview.
+pub fn process_decode(
+    input: &str,
+    mut output: impl Write,
+    format: Base64Format,
+    hexdump: bool,
+) -> anyhow::Result<()> {
+    let reader = SkipWhitespace(get_reader(input)?);
+    let mut decoder = DecoderReader::new(reader, engine(&format));
+    if hexdump {
+        let mut dump = HexDump {
+            inner: &mut output,
+            offset: 0,
+            line: Vec::with_capacity(16),
+        };
+        io::copy(&mut decoder, &mut dump)?;
+        dump.finish()?;
+    } else {
+        io::copy(&mut decoder, &mut output)?;
+    }
+    output.flush()?;
+    Ok(())
 }
 
-pub fn process_decode(input: &str, format: Base64Format) -> anyhow::Result<Vec<u8>> {
-    let mut reader: Box<dyn Read> = get_reader(input)?;
+fn engine(format: &Base64Format) -> &'static GeneralPurpose {
+    match format {
+        Base64Format::Standard => &general_purpose::STANDARD,
+        Base64Format::Urlsafe => &general_purpose::URL_SAFE_NO_PAD,
+    }
+}
 
-    let mut buf = String::new();
-    reader.read_to_string(&mut buf)?;
-    let buf = buf.trim();
+// starts a new line whenever `width` characters have been written on the current one
+struct LineWrap<W> {
+    inner: W,
+    width: usize,
+    column: usize,
+}
 
-    let decoded = match format {
-        Base64Format::Standard => general_purpose::STANDARD.decode(buf)?,
-        Base64Format::Urlsafe => general_purpose::URL_SAFE_NO_PAD.decode(buf)?,
-    };
-    Ok(decoded)
+impl<W: Write> Write for LineWrap<W> {
+    // always takes the whole buffer, the encoder treats short writes as errors
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut rest = buf;
+        while !rest.is_empty() {
+            if self.column == self.width {
+                self.inner.write_all(b"\n")?;
+                self.column = 0;
+            }
+            let n = rest.len().min(self.width - self.column);
+            self.inner.write_all(&rest[..n])?;
+            self.column += n;
+            rest = &rest[n..];
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+// the offset, sixteen bytes in hex and their printable characters per line,
+// e.g. `00000000  68 65 6c 6c 6f 0a  ...  |hello.|`, then the total length
+struct HexDump<W> {
+    inner: W,
+    offset: usize,
+    line: Vec<u8>,
+}
+
+impl<W: Write> HexDump<W> {
+    fn write_line(&mut self) -> io::Result<()> {
+        let mut hex = String::new();
+        for (i, byte) in self.line.iter().enumerate() {
+            if i == 8 {
+                hex.push(' ');
+            }
+            hex.push_str(&format!("{:02x} ", byte));
+        }
+        let text = self
+            .line
+            .iter()
+            .map(|b| {
+                if b.is_ascii_graphic() || *b == b' ' {
+                    *b as char
+                } else {
+                    '.'
+                }
+            })
+            .collect::<String>();
+        writeln!(self.inner, "{:08x}  {:<49} |{}|", self.offset, hex, text)?;
+        self.offset += self.line.len();
+        self.line.clear();
+        Ok(())
+    }
+
+    fn finish(mut self) -> io::Result<()> {
+        if !self.line.is_empty() {
+            self.write_line()?;
+        }
+        writeln!(self.inner, "{:08x}", self.offset)
+    }
+}
+
+impl<W: Write> Write for HexDump<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        for byte in buf {
+            self.line.push(*byte);
+            if self.line.len() == 16 {
+                self.write_line()?;
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+struct SkipWhitespace<R>(R);
+
+impl<R: Read> Read for SkipWhitespace<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let n = self.0.read(buf)?;
+            if n == 0 {
+                return Ok(0);
+            }
+            let mut kept = 0;
+            for i in 0..n {
+                if !buf[i].is_ascii_whitespace() {
+                    buf[kept] = buf[i];
+                    kept += 1;
+                }
+            }
+            if kept > 0 {
+                return Ok(kept);
+            }
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use base64::Engine;
+    use std::fs;
+
+    #[test]
+    fn test_process_encode() -> anyhow::Result<()> {
+        let input = "fixtures/ed25519.pk";
+        let mut encoded = Vec::new();
+        process_encode(input, &mut encoded, Base64Format::Standard, None)?;
+        let expected = general_purpose::STANDARD.encode(fs::read(input)?);
+        assert_eq!(String::from_utf8(encoded)?, format!("{}\n", expected));
+        Ok(())
+    }
 
     #[test]
-    fn test_process_encode() {
-        let input = "-";
-        let format = Base64Format::Standard;
-        assert!(process_encode(input, format).is_ok());
+    fn test_process_encode_wrapped() -> anyhow::Result<()> {
+        let input = "fixtures/tmp.b64";
+        let mut encoded = Vec::new();
+        process_encode(input, &mut encoded, Base64Format::Standard, Some(64))?;
+        let encoded = String::from_utf8(encoded)?;
+        let lines = encoded.lines().collect::<Vec<_>>();
+        assert!(lines.len() > 1);
+        assert!(lines[..lines.len() - 1].iter().all(|l| l.len() == 64));
+        assert!(encoded.ends_with('\n') && !encoded.ends_with("\n\n"));
+
+        let file = tempfile::NamedTempFile::new()?;
+        fs::write(file.path(), &encoded)?;
+        let mut decoded = Vec::new();
+        process_decode(
+            file.path().to_str().unwrap(),
+            &mut decoded,
+            Base64Format::Standard,
+            false,
+        )?;
+        assert_eq!(decoded, fs::read(input)?);
+        Ok(())
     }
 
     #[test]
-    fn test_process_decode() {
+    fn test_process_decode() -> anyhow::Result<()> {
         let input = "fixtures/tmp.b64";
         let format = Base64Format::Urlsafe;
-        assert!(process_decode(input, format).is_ok());
+        let mut decoded = Vec::new();
+        process_decode(input, &mut decoded, format, false)?;
+        assert!(decoded.starts_with(b"[package]"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_decode_binary() -> anyhow::Result<()> {
+        let bytes = (0..=255).collect::<Vec<u8>>();
+        let file = tempfile::NamedTempFile::new()?;
+        fs::write(file.path(), general_purpose::STANDARD.encode(&bytes))?;
+        let input = file.path().to_str().unwrap();
+
+        let mut decoded = Vec::new();
+        process_decode(input, &mut decoded, Base64Format::Standard, false)?;
+        assert_eq!(decoded, bytes);
+
+        let mut dump = Vec::new();
+        process_decode(input, &mut dump, Base64Format::Standard, true)?;
+        let dump = String::from_utf8(dump)?;
+        let lines = dump.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 17);
+        assert_eq!(
+            lines[2],
+            "00000020  20 21 22 23 24 25 26 27  28 29 2a 2b 2c 2d 2e 2f  | !\"#$%&'()*+,-./|"
+        );
+        assert_eq!(lines[16], "00000100");
+        Ok(())
+    }
+
+    #[test]
+    fn test_hexdump_partial_line() -> anyhow::Result<()> {
+        let mut out = Vec::new();
+        let mut dump = HexDump {
+            inner: &mut out,
+            offset: 0,
+            line: Vec::new(),
+        };
+        dump.write_all(b"hello\n")?;
+        dump.finish()?;
+        assert_eq!(
+            String::from_utf8(out)?,
+            format!("00000000  68 65 6c 6c 6f 0a {:31} |hello.|\n00000006\n", "")
+        );
+        Ok(())
     }
 }
@@ -0,0 +1,442 @@
+use std::{
+    io::{BufRead, BufReader, Cursor, Read, Write},
+    path::Path,
+};
+
+use anyhow::{anyhow, Result};
+use serde_json::{Map, Value};
+use tracing::info;
+
+use super::{csv_convert::csv_reader, csv_export::write_csv, csv_schema::Schema};
+use crate::{
+    cli::{CsvReaderOpts, OutputFormat},
+    get_reader,
+};
+
+// Every format goes through the same intermediate representation: a list of
+// JSON records. Supporting a new format means adding it to `read_records` and
+// `RecordWriter`.
+
+pub fn process_convert(
+    input: &str,
+    output: impl Write,
+    from: Option<OutputFormat>,
+    to: OutputFormat,
+    csv_opts: &CsvReaderOpts,
+    infer: bool,
+    toml_root: &str,
+) -> Result<()> {
+    let mut buf = Vec::new();
+    get_reader(input)?.read_to_end(&mut buf)?;
+    let from = match from {
+        Some(from) => from,
+        None => {
+            let from = detect_format(input, &buf);
+            info!("detected input format: {}", from);
+            from
+        }
+    };
+    let records = read_records(Box::new(Cursor::new(buf)), from, csv_opts, infer, toml_root)?;
+
+    let mut writer = RecordWriter::new(output, to, toml_root, csv_opts);
+    for record in records {
+        writer.write(record)?;
+    }
+    writer.finish()?.flush()?;
+    Ok(())
+}
+
+/// Guess the format of a document from its file extension, falling back to
+/// sniffing the content.
+pub fn detect_format(path: &str, content: &[u8]) -> OutputFormat {
+    let ext = Path::new(path).extension().and_then(|ext| ext.to_str());
+    if let Some(format) = ext.and_then(|ext| ext.parse().ok()) {
+        return format;
+    }
+
+    let text = String::from_utf8_lossy(content);
+    let text = text.trim_start();
+    let first_line = text.lines().next().unwrap_or_default();
+    if serde_json::from_str::<Value>(text).is_ok() {
+        OutputFormat::Json
+    } else if first_line.starts_with('{') && serde_json::from_str::<Value>(first_line).is_ok() {
+        OutputFormat::Ndjson
+    } else if !text.is_empty() && toml::from_str::<toml::Table>(text).is_ok() {
+        OutputFormat::Toml
+    } else if matches!(
+        serde_yaml::from_str::<Value>(text),
+        Ok(Value::Array(_) | Value::Object(_))
+    ) {
+        OutputFormat::Yaml
+    } else {
+        OutputFormat::Csv
+    }
+}
+
+/// Read an array of records from a document. A single object is treated as one
+/// record; TOML records live in the array of tables under `toml_root`.
+pub fn read_records(
+    mut reader: Box<dyn Read>,
+    from: OutputFormat,
+    csv_opts: &CsvReaderOpts,
+    infer: bool,
+    toml_root: &str,
+) -> Result<Vec<Value>> {
+    let value = match from {
+        OutputFormat::Csv => {
+            let (headers, mut reader) = csv_reader(reader, csv_opts)?;
+            let records = reader.records().collect::<Result<Vec<_>, _>>()?;
+            let types = Schema::default().resolve(&headers, &records, infer)?;
+            let records = records
+                .iter()
+                .map(|record| types.to_value(&headers, record))
+                .collect::<Result<_>>()?;
+            Value::Array(records)
+        }
+        OutputFormat::Ndjson => {
+            let mut records = Vec::new();
+            for line in BufReader::new(reader).lines() {
+                let line = line?;
+                if !line.trim().is_empty() {
+                    records.push(serde_json::from_str(&line)?);
+                }
+            }
+            Value::Array(records)
+        }
+        OutputFormat::Json => serde_json::from_reader(reader)?,
+        OutputFormat::Yaml => serde_yaml::from_reader(reader)?,
+        OutputFormat::Toml => {
+            let mut buf = String::new();
+            reader.read_to_string(&mut buf)?;
+            let mut doc: Map<String, Value> = toml::from_str(&buf)?;
+            doc.remove(toml_root)
+                .ok_or_else(|| anyhow!("TOML document has no {:?} array", toml_root))?
+        }
+    };
+    let records = match value {
+        Value::Array(records) => records,
+        Value::Object(_) => vec![value],
+        _ => return Err(anyhow!("expected an array of objects")),
+    };
+    // every writer relies on this, e.g. CSV for its columns and TOML for its tables
+    match records.iter().position(|record| !record.is_object()) {
+        Some(i) => Err(anyhow!("expected an array of objects, record {} is not", i)),
+        None => Ok(records),
+    }
+}
+
+/// Serialize records one at a time, so output never needs the whole data set in
+/// memory. CSV is the exception: records are kept until `finish` since the header
+/// is the union of all keys.
+pub struct RecordWriter<W: Write> {
+    writer: W,
+    format: OutputFormat,
+    toml_root: String,
+    csv_opts: CsvReaderOpts,
+    csv_records: Vec<Value>,
+    count: usize,
+}
+
+impl<W: Write> RecordWriter<W> {
+    pub fn new(writer: W, format: OutputFormat, toml_root: &str, csv_opts: &CsvReaderOpts) -> Self {
+        Self {
+            writer,
+            format,
+            toml_root: toml_root.to_string(),
+            csv_opts: csv_opts.clone(),
+            csv_records: Vec::new(),
+            count: 0,
+        }
+    }
+
+    pub fn write(&mut self, record: Value) -> Result<()> {
+        let first = self.count == 0;
+        match self.format {
+            OutputFormat::Json => {
+                // same layout as `serde_json::to_string_pretty` on the whole array, plus
+                // a final newline like the other writers
+                self.writer.write_all(if first { b"[\n" } else { b",\n" })?;
+                let content = serde_json::to_string_pretty(&record)?;
+                for (i, line) in content.lines().enumerate() {
+                    if i > 0 {
+                        self.writer.write_all(b"\n")?;
+                    }
+                    write!(self.writer, "  {}", line)?;
+                }
+            }
+            OutputFormat::Ndjson => {
+                serde_json::to_writer(&mut self.writer, &record)?;
+                self.writer.write_all(b"\n")?;
+            }
+            OutputFormat::Toml => {
+                // a TOML document must be a table, so records become an array of
+                // tables; each one is emitted as its own `[[root]]` section
+                if !first {
+                    self.writer.write_all(b"\n")?;
+                }
+                let record = match to_toml_value(record, "")? {
+                    Some(table @ toml::Value::Table(_)) => table,
+                    _ => return Err(anyhow!("record {} is not an object", self.count)),
+                };
+                let mut doc = toml::Table::new();
+                doc.insert(self.toml_root.clone(), toml::Value::Array(vec![record]));
+                self.writer.write_all(toml::to_string(&doc)?.as_bytes())?;
+            }
+            OutputFormat::Yaml => serde_yaml::to_writer(&mut self.writer, &[record])?,
+            OutputFormat::Csv => self.csv_records.push(record),
+        }
+        self.count += 1;
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> Result<W> {
+        match (self.format, self.count) {
+            (OutputFormat::Json, 0) => self.writer.write_all(b"[]\n")?,
+            (OutputFormat::Json, _) => self.writer.write_all(b"\n]\n")?,
+            (OutputFormat::Toml, 0) => writeln!(self.writer, "{} = []", self.toml_root)?,
+            (OutputFormat::Yaml, 0) => self.writer.write_all(b"[]\n")?,
+            (OutputFormat::Csv, _) => {
+                write_csv(self.csv_records, &mut self.writer, &self.csv_opts)?
+            }
+            _ => {}
+        }
+        Ok(self.writer)
+    }
+}
+
+/// Convert a JSON value into TOML, which has no null: null table entries are
+/// left out, while a null array element is an error since dropping it would
+/// shift the elements after it. `path` names the value in that error.
+fn to_toml_value(value: Value, path: &str) -> Result<Option<toml::Value>> {
+    let value = match value {
+        Value::Null => return Ok(None),
+        Value::Bool(b) => toml::Value::Boolean(b),
+        Value::Number(n) => match n.as_i64() {
+            Some(i) => toml::Value::Integer(i),
+            None => toml::Value::Float(
+                n.as_f64()
+                    .ok_or_else(|| anyhow!("cannot write {} at `{}` to TOML", n, path))?,
+            ),
+        },
+        Value::String(s) => toml::Value::String(s),
+        Value::Array(values) => {
+            let mut array = Vec::with_capacity(values.len());
+            for (i, v) in values.into_iter().enumerate() {
+                let path = format!("{}[{}]", path, i);
+                let v = to_toml_value(v, &path)?.ok_or_else(|| {
+                    anyhow!(
+                        "cannot write the null at `{}` to TOML, which has no null",
+                        path
+                    )
+                })?;
+                array.push(v);
+            }
+            toml::Value::Array(array)
+        }
+        Value::Object(map) => {
+            let mut table = toml::Table::new();
+            for (k, v) in map {
+                let path = if path.is_empty() {
+                    k.clone()
+                } else {
+                    format!("{}.{}", path, k)
+                };
+                if let Some(v) = to_toml_value(v, &path)? {
+                    table.insert(k, v);
+                }
+            }
+            toml::Value::Table(table)
+        }
+    };
+    Ok(Some(value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn serialize_records(ret: Vec<Value>, format: OutputFormat, toml_root: &str) -> Result<String> {
+        let opts = CsvReaderOpts::default();
+        let mut writer = RecordWriter::new(Vec::new(), format, toml_root, &opts);
+        for record in ret {
+            writer.write(record)?;
+        }
+        Ok(String::from_utf8(writer.finish()?)?)
+    }
+
+    fn read_juventus() -> Result<Vec<Value>> {
+        let rdr = get_reader("assets/juventus.csv")?;
+        read_records(
+            rdr,
+            OutputFormat::Csv,
+            &CsvReaderOpts::default(),
+            true,
+            "records",
+        )
+    }
+
+    #[test]
+    fn test_read_records_from_each_format() -> Result<()> {
+        let expected = vec![
+            json!({"name": "a", "kit": 1}),
+            json!({"name": "b", "kit": 2}),
+        ];
+        let inputs = [
+            (
+                OutputFormat::Json,
+                r#"[{"name":"a","kit":1},{"name":"b","kit":2}]"#,
+            ),
+            (
+                OutputFormat::Ndjson,
+                "{\"name\":\"a\",\"kit\":1}\n\n{\"name\":\"b\",\"kit\":2}\n",
+            ),
+            (
+                OutputFormat::Yaml,
+                "- name: a\n  kit: 1\n- name: b\n  kit: 2\n",
+            ),
+            (
+                OutputFormat::Toml,
+                "[[records]]\nname = \"a\"\nkit = 1\n\n[[records]]\nname = \"b\"\nkit = 2\n",
+            ),
+            (OutputFormat::Csv, "name,kit\na,1\nb,2\n"),
+        ];
+        let opts = CsvReaderOpts::default();
+        for (format, input) in inputs {
+            let records = read_records(Box::new(input.as_bytes()), format, &opts, true, "records")?;
+            assert_eq!(records, expected, "{}", format);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_records_rejects_non_objects() {
+        let opts = CsvReaderOpts::default();
+        for (format, input) in [
+            (OutputFormat::Json, "[1, 2]"),
+            (OutputFormat::Json, r#"[{"a": 1}, null]"#),
+            (OutputFormat::Ndjson, "{\"a\": 1}\n\"b\"\n"),
+            (OutputFormat::Yaml, "- a: 1\n- [2]\n"),
+            (OutputFormat::Json, "3"),
+        ] {
+            let err = read_records(Box::new(input.as_bytes()), format, &opts, true, "records")
+                .unwrap_err();
+            assert!(
+                err.to_string().starts_with("expected an array of objects"),
+                "{}",
+                err
+            );
+        }
+    }
+
+    #[test]
+    fn test_detect_format() {
+        assert!(matches!(detect_format("a.yml", b""), OutputFormat::Yaml));
+        assert!(matches!(
+            detect_format("a.JSONL", b""),
+            OutputFormat::Ndjson
+        ));
+        assert!(matches!(
+            detect_format("-", b" [{\"a\": 1}]"),
+            OutputFormat::Json
+        ));
+        assert!(matches!(
+            detect_format("-", b"{\"a\": 1}\n{\"a\": 2}\n"),
+            OutputFormat::Ndjson
+        ));
+        assert!(matches!(
+            detect_format("-", b"[[records]]\na = 1\n"),
+            OutputFormat::Toml
+        ));
+        assert!(matches!(
+            detect_format("-", b"- a: 1\n- a: 2\n"),
+            OutputFormat::Yaml
+        ));
+        assert!(matches!(
+            detect_format("data.txt", b"a,b\n1,2\n"),
+            OutputFormat::Csv
+        ));
+    }
+
+    #[test]
+    fn test_every_format_pair_round_trips() -> Result<()> {
+        let ret = read_juventus()?;
+        let opts = CsvReaderOpts::default();
+        for to in [
+            OutputFormat::Json,
+            OutputFormat::Ndjson,
+            OutputFormat::Toml,
+            OutputFormat::Yaml,
+            OutputFormat::Csv,
+        ] {
+            let content = serialize_records(ret.clone(), to, "records")?;
+            assert_eq!(
+                detect_format("-", content.as_bytes()).to_string(),
+                to.to_string()
+            );
+            let back = read_records(Box::new(Cursor::new(content)), to, &opts, true, "records")?;
+            assert_eq!(back, ret, "{}", to);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_toml_round_trip() -> Result<()> {
+        let ret = read_juventus()?;
+        let content = serialize_records(ret.clone(), OutputFormat::Toml, "records")?;
+        let mut doc: toml::Table = toml::from_str(&content)?;
+        let records = doc
+            .remove("records")
+            .ok_or_else(|| anyhow!("missing records"))?;
+        assert_eq!(serde_json::to_value(records)?, Value::Array(ret));
+        Ok(())
+    }
+
+    #[test]
+    fn test_toml_omits_nulls_and_keeps_nesting() -> Result<()> {
+        let ret = vec![
+            json!({"name": "a", "kit": null, "tags": ["x", "y"], "address": {"city": "Turin", "zip": null}}),
+        ];
+        let content = serialize_records(ret, OutputFormat::Toml, "players")?;
+        let doc: toml::Table = toml::from_str(&content)?;
+        let expected =
+            json!({"players": [{"name": "a", "tags": ["x", "y"], "address": {"city": "Turin"}}]});
+        assert_eq!(serde_json::to_value(doc)?, expected);
+
+        // dropping the null would move "z" from t[2] to t[1]
+        let ret = vec![json!({"name": "a", "t": ["x", null, "z"]})];
+        let err = serialize_records(ret, OutputFormat::Toml, "players").unwrap_err();
+        assert!(err.to_string().contains("`t[1]`"), "{}", err);
+
+        // each would become its own `players = [...]`, repeating the key
+        let err =
+            serialize_records(vec![json!(1), json!(2)], OutputFormat::Toml, "players").unwrap_err();
+        assert_eq!(err.to_string(), "record 0 is not an object");
+        Ok(())
+    }
+
+    #[test]
+    fn test_json_writer_matches_pretty_array() -> Result<()> {
+        let ret = read_juventus()?;
+        let content = serialize_records(ret.clone(), OutputFormat::Json, "records")?;
+        assert_eq!(content, serde_json::to_string_pretty(&ret)? + "\n");
+        let content = serialize_records(vec![], OutputFormat::Json, "records")?;
+        assert_eq!(content, "[]\n");
+        Ok(())
+    }
+
+    #[test]
+    fn test_ndjson_and_yaml_writer() -> Result<()> {
+        let ret = read_juventus()?;
+        let content = serialize_records(ret.clone(), OutputFormat::Ndjson, "records")?;
+        let lines = content
+            .lines()
+            .map(serde_json::from_str)
+            .collect::<Result<Vec<Value>, _>>()?;
+        assert_eq!(lines, ret);
+
+        let content = serialize_records(ret.clone(), OutputFormat::Yaml, "records")?;
+        assert_eq!(serde_yaml::from_str::<Vec<Value>>(&content)?, ret);
+        Ok(())
+    }
+}
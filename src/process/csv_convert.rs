@@ -1,39 +1,200 @@
-use anyhow::Result;
+use anyhow::{anyhow, Result};
+use std::io::{Read, Write};
+
+use csv::{Reader, ReaderBuilder, StringRecord, Trim};
 use serde_json::Value;
-use std::fs;
-
-use csv::Reader;
-use serde::{Deserialize, Serialize};
-
-use crate::cli::OutputFormat;
-
-#[derive(Debug, Deserialize, Serialize)]
-#[serde(rename_all = "PascalCase")]
-struct Player {
-    name: String,
-    position: String,
-    #[serde(rename = "DOB")]
-    dob: String,
-    nationality: String,
-    #[serde(rename = "Kit Number")]
-    kit: u8,
-}
+use tracing::info;
 
-pub fn process_csv(input: &str, output: &str, format: OutputFormat) -> Result<()> {
-    let mut reader = Reader::from_path(input)?;
-    let mut ret = Vec::with_capacity(128);
-    let headers = reader.headers().cloned()?;
-    for result in reader.records() {
-        let record = result?;
-        let json_value = headers.iter().zip(record.iter()).collect::<Value>();
-        ret.push(json_value);
-    }
+use super::{
+    convert::RecordWriter,
+    csv_dialect::{decode_reader, sniff_reader},
+    csv_export::unflatten,
+    csv_query::Query,
+    csv_schema::Schema,
+};
+use crate::{
+    cli::{CsvConvertOpts, CsvQueryOpts, CsvReaderOpts, OutputFormat},
+    get_reader,
+};
+
+pub fn process_csv(
+    input: &str,
+    output: impl Write,
+    format: OutputFormat,
+    reader_opts: &CsvReaderOpts,
+    convert_opts: &CsvConvertOpts,
+    query_opts: &CsvQueryOpts,
+) -> Result<()> {
+    let schema = match &convert_opts.schema {
+        Some(path) => Schema::load(path)?,
+        None => Schema::default(),
+    };
+    let (headers, mut reader) = csv_reader(get_reader(input)?, reader_opts)?;
+    let mut query = Query::new(query_opts, &headers)?;
+    let mut records = reader.records();
+
+    // types are inferred from the whole file, or only the leading sample when streaming
+    let sample_size = match (convert_opts.stream, convert_opts.infer()) {
+        (false, _) => usize::MAX,
+        (true, true) => convert_opts.sample,
+        (true, false) => 0,
+    };
+    let sample = records
+        .by_ref()
+        .take(sample_size)
+        .collect::<Result<Vec<_>, _>>()?;
+    let types = schema.resolve(&headers, &sample, convert_opts.infer())?;
 
-    let content = match format {
-        OutputFormat::Json => serde_json::to_string_pretty(&ret)?,
-        OutputFormat::Toml => toml::to_string(&ret)?, // TODO toml Error: unsupported rust type
-        OutputFormat::Yaml => serde_yaml::to_string(&ret)?,
+    let mut writer = RecordWriter::new(output, format, &convert_opts.toml_root, reader_opts);
+    let values = sample
+        .iter()
+        .map(|record| types.to_value(&headers, record))
+        .chain(records.map(|record| types.to_value_lenient(&headers, &record?)));
+    let mut write = |value: Value| {
+        if convert_opts.unflatten {
+            writer.write(unflatten(value)?)
+        } else {
+            writer.write(value)
+        }
     };
-    fs::write(format!("{}.{}", output, format), content)?;
+    for value in values {
+        if query.is_done() {
+            break;
+        }
+        if let Some(value) = query.push(value?) {
+            write(value)?;
+        }
+    }
+    for value in query.finish() {
+        write(value)?;
+    }
+    writer.finish()?.flush()?;
     Ok(())
 }
+
+/// Build a csv reader from the options and resolve the column names, which are
+/// generated as `col0`, `col1`, ... for headerless input.
+pub fn csv_reader(
+    rdr: Box<dyn Read>,
+    opts: &CsvReaderOpts,
+) -> Result<(StringRecord, Reader<Box<dyn Read>>)> {
+    let (rdr, encoding) = decode_reader(rdr, opts.encoding)?;
+    info!("encoding: {}", encoding.name());
+    let (rdr, opts) = if opts.sniff {
+        let (rdr, dialect) = sniff_reader(rdr)?;
+        info!(
+            "sniffed delimiter {:?}, quote {:?}, header {}",
+            char::from(dialect.delimiter),
+            char::from(dialect.quote),
+            dialect.header
+        );
+        let opts = CsvReaderOpts {
+            delimiter: dialect.delimiter,
+            quote: dialect.quote,
+            header: dialect.header,
+            ..opts.clone()
+        };
+        (rdr, opts)
+    } else {
+        (rdr, opts.clone())
+    };
+
+    let mut reader = ReaderBuilder::new()
+        .has_headers(opts.header)
+        .delimiter(opts.delimiter)
+        .quote(opts.quote)
+        .escape(opts.escape)
+        .double_quote(opts.escape.is_none())
+        .comment(opts.comment)
+        .flexible(opts.flexible)
+        .trim(if opts.trim { Trim::All } else { Trim::None })
+        .from_reader(rdr);
+
+    // without a header row this peeks at the first record, which is still yielded
+    let first = reader.headers()?.clone();
+    let headers = match &opts.columns {
+        Some(columns) if columns.len() != first.len() && !opts.flexible => {
+            return Err(anyhow!(
+                "{} column names given but records have {} fields",
+                columns.len(),
+                first.len()
+            ))
+        }
+        Some(columns) => StringRecord::from(columns.clone()),
+        None if opts.header => first,
+        None => (0..first.len()).map(|i| format!("col{}", i)).collect(),
+    };
+    Ok((headers, reader))
+}
+
+/// The field at `i` of a record, empty when a short record (`--flexible`) has
+/// no such field.
+pub(super) fn field(record: &StringRecord, i: usize) -> &str {
+    record.get(i).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::process::csv_schema::read_typed_records;
+    use serde_json::json;
+
+    fn read_str(data: &'static str, opts: &CsvReaderOpts) -> Result<Vec<Value>> {
+        let (headers, mut reader) = csv_reader(Box::new(data.as_bytes()), opts)?;
+        read_typed_records(&headers, reader.records(), true, &Schema::default())
+    }
+
+    #[test]
+    fn test_csv_reader_without_header() -> Result<()> {
+        let opts = CsvReaderOpts {
+            header: false,
+            delimiter: b'\t',
+            ..Default::default()
+        };
+        let ret = read_str("a\t1\nb\t2\n", &opts)?;
+        assert_eq!(
+            ret,
+            vec![
+                json!({"col0": "a", "col1": 1}),
+                json!({"col0": "b", "col1": 2})
+            ]
+        );
+
+        let opts = CsvReaderOpts {
+            columns: Some(vec!["name".into(), "kit".into()]),
+            ..opts
+        };
+        let ret = read_str("a\t1\n", &opts)?;
+        assert_eq!(ret, vec![json!({"name": "a", "kit": 1})]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_csv_reader_dialect() -> Result<()> {
+        let opts = CsvReaderOpts {
+            delimiter: b';',
+            quote: b'\'',
+            escape: Some(b'\\'),
+            comment: Some(b'#'),
+            flexible: true,
+            trim: true,
+            ..Default::default()
+        };
+        let ret = read_str("name; note\n# skipped\n'a;\\'b' ; x\nc\n", &opts)?;
+        assert_eq!(
+            ret,
+            vec![json!({"name": "a;'b", "note": "x"}), json!({"name": "c"})]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_csv_reader_rejects_column_count_mismatch() {
+        let opts = CsvReaderOpts {
+            header: false,
+            columns: Some(vec!["a".into()]),
+            ..Default::default()
+        };
+        assert!(read_str("1,2\n", &opts).is_err());
+    }
+}
@@ -0,0 +1,269 @@
+use std::io::{Cursor, Read};
+
+use anyhow::Result;
+use csv::ReaderBuilder;
+use encoding_rs::{Encoding, UTF_16BE, UTF_16LE, UTF_8, WINDOWS_1252};
+use encoding_rs_io::DecodeReaderBytesBuilder;
+
+use super::csv_schema::ColumnType;
+
+// bytes looked at to guess the encoding and the dialect
+const SAMPLE_SIZE: usize = 64 * 1024;
+
+const DELIMITERS: [u8; 4] = [b',', b';', b'\t', b'|'];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Dialect {
+    pub delimiter: u8,
+    pub quote: u8,
+    pub header: bool,
+}
+
+/// Wrap a reader so it yields UTF-8 without a BOM. The encoding is the given
+/// one, or the one named by a BOM, or guessed from the leading bytes: UTF-16
+/// when every other byte is zero, Windows-1252 when the input is not UTF-8.
+pub fn decode_reader(
+    mut rdr: Box<dyn Read>,
+    encoding: Option<&'static Encoding>,
+) -> Result<(Box<dyn Read>, &'static Encoding)> {
+    let sample = read_sample(&mut rdr)?;
+    let bom = Encoding::for_bom(&sample);
+    let encoding = match (encoding, bom) {
+        (Some(encoding), _) => encoding,
+        (None, Some((encoding, _))) => encoding,
+        (None, None) => detect_encoding(&sample),
+    };
+
+    if encoding == UTF_8 {
+        // no need to transcode, only skip the BOM
+        let mut sample = Cursor::new(sample);
+        if let Some((bom, len)) = bom {
+            if bom == UTF_8 {
+                sample.set_position(len as u64);
+            }
+        }
+        return Ok((Box::new(sample.chain(rdr)), encoding));
+    }
+    let decoder = DecodeReaderBytesBuilder::new()
+        .encoding(Some(encoding))
+        .build(Cursor::new(sample).chain(rdr));
+    Ok((Box::new(decoder), encoding))
+}
+
+/// Guess the dialect from the start of a reader, returning a reader that
+/// still yields the whole input.
+pub fn sniff_reader(mut rdr: Box<dyn Read>) -> Result<(Box<dyn Read>, Dialect)> {
+    let sample = read_sample(&mut rdr)?;
+    let dialect = sniff(&sample);
+    Ok((Box::new(Cursor::new(sample).chain(rdr)), dialect))
+}
+
+fn read_sample(rdr: &mut Box<dyn Read>) -> Result<Vec<u8>> {
+    let mut sample = Vec::new();
+    rdr.by_ref()
+        .take(SAMPLE_SIZE as u64)
+        .read_to_end(&mut sample)?;
+    Ok(sample)
+}
+
+fn detect_encoding(sample: &[u8]) -> &'static Encoding {
+    // ASCII text in UTF-16 has a zero in every other byte
+    let zeros = |offset: usize| {
+        sample
+            .iter()
+            .skip(offset)
+            .step_by(2)
+            .filter(|b| **b == 0)
+            .count()
+    };
+    let half = sample.len() / 2;
+    if half > 0 && zeros(1) * 2 > half && zeros(0) * 10 < half {
+        return UTF_16LE;
+    }
+    if half > 0 && zeros(0) * 2 > half && zeros(1) * 10 < half {
+        return UTF_16BE;
+    }
+    match std::str::from_utf8(sample) {
+        Ok(_) => UTF_8,
+        // the sample may end in the middle of a character
+        Err(e) if e.error_len().is_none() => UTF_8,
+        Err(_) => WINDOWS_1252,
+    }
+}
+
+/// Guess the delimiter, quote and header presence of a CSV sample.
+///
+/// The delimiter is the candidate splitting the most rows into the same number
+/// (more than one) of fields. The first row is taken as a header unless it
+/// looks like data, e.g. a number at the top of a numeric column.
+pub fn sniff(sample: &[u8]) -> Dialect {
+    // drop a trailing partial line
+    let sample = match sample.iter().rposition(|b| *b == b'\n') {
+        Some(end) => &sample[..=end],
+        None => sample,
+    };
+    let quote = sniff_quote(sample);
+
+    let mut best: Option<(f64, usize, u8)> = None;
+    for delimiter in DELIMITERS {
+        let counts = parse(sample, delimiter, quote)
+            .iter()
+            .map(Vec::len)
+            .collect::<Vec<_>>();
+        let Some(mode) = mode(&counts) else {
+            continue;
+        };
+        if mode < 2 {
+            continue;
+        }
+        let consistency =
+            counts.iter().filter(|n| **n == mode).count() as f64 / counts.len() as f64;
+        if best.is_none_or(|(c, m, _)| (consistency, mode) > (c, m)) {
+            best = Some((consistency, mode, delimiter));
+        }
+    }
+    let delimiter = best.map_or(b',', |(_, _, delimiter)| delimiter);
+    let rows = parse(sample, delimiter, quote);
+    Dialect {
+        delimiter,
+        quote,
+        header: sniff_header(&rows),
+    }
+}
+
+fn sniff_quote(sample: &[u8]) -> u8 {
+    // count quotes opening a field, right after a delimiter or a line break
+    let opening = |quote: u8| {
+        sample
+            .windows(2)
+            .filter(|w| w[1] == quote && (w[0] == b'\n' || DELIMITERS.contains(&w[0])))
+            .count()
+            + usize::from(sample.first() == Some(&quote))
+    };
+    if opening(b'\'') > opening(b'"') {
+        b'\''
+    } else {
+        b'"'
+    }
+}
+
+fn sniff_header(rows: &[Vec<String>]) -> bool {
+    let Some((first, data)) = rows.split_first() else {
+        return true;
+    };
+    let data = &data[..data.len().min(100)];
+    if data.is_empty() {
+        return true;
+    }
+    let mut votes = 0i32;
+    for (i, name) in first.iter().enumerate() {
+        let cells = data
+            .iter()
+            .filter_map(|row| row.get(i))
+            .filter(|c| !c.is_empty())
+            .collect::<Vec<_>>();
+        let column_type = cells
+            .iter()
+            .map(|c| ColumnType::detect(c))
+            .fold(ColumnType::Null, ColumnType::merge);
+        match column_type {
+            ColumnType::Null => {}
+            ColumnType::String => {
+                // fixed-width columns (codes, ids) with a name of another width
+                let len = cells[0].chars().count();
+                if cells.iter().all(|c| c.chars().count() == len) {
+                    votes += if name.chars().count() == len { -1 } else { 1 };
+                }
+            }
+            t if t.merge(ColumnType::detect(name)) == t => votes -= 1,
+            _ => votes += 1,
+        }
+    }
+    votes >= 0
+}
+
+fn parse(sample: &[u8], delimiter: u8, quote: u8) -> Vec<Vec<String>> {
+    ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .delimiter(delimiter)
+        .quote(quote)
+        .from_reader(sample)
+        .records()
+        .map_while(Result::ok)
+        .map(|record| record.iter().map(String::from).collect())
+        .collect()
+}
+
+fn mode(counts: &[usize]) -> Option<usize> {
+    let mut sorted = counts.to_vec();
+    sorted.sort_unstable();
+    sorted
+        .chunk_by(|a, b| a == b)
+        .max_by_key(|chunk| (chunk.len(), chunk[0]))
+        .map(|chunk| chunk[0])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode(bytes: Vec<u8>) -> Result<(String, &'static str)> {
+        let (mut rdr, encoding) = decode_reader(Box::new(Cursor::new(bytes)), None)?;
+        let mut ret = String::new();
+        rdr.read_to_string(&mut ret)?;
+        Ok((ret, encoding.name()))
+    }
+
+    #[test]
+    fn test_decode_reader() -> Result<()> {
+        let text = "Name,Club\nMüller,Bayern\n";
+        assert_eq!(decode(text.into())?, (text.to_string(), "UTF-8"));
+
+        let bom = [&[0xEF, 0xBB, 0xBF], text.as_bytes()].concat();
+        assert_eq!(decode(bom)?, (text.to_string(), "UTF-8"));
+
+        let utf16 = text.encode_utf16().flat_map(u16::to_le_bytes);
+        assert_eq!(
+            decode(utf16.clone().collect())?,
+            (text.to_string(), "UTF-16LE")
+        );
+        let with_bom = [0xFF, 0xFE].into_iter().chain(utf16).collect();
+        assert_eq!(decode(with_bom)?, (text.to_string(), "UTF-16LE"));
+
+        let utf16be = text.encode_utf16().flat_map(u16::to_be_bytes).collect();
+        assert_eq!(decode(utf16be)?, (text.to_string(), "UTF-16BE"));
+
+        let (cp1252, _, _) = WINDOWS_1252.encode(text);
+        assert_eq!(
+            decode(cp1252.into_owned())?,
+            (text.to_string(), "windows-1252")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_sniff_dialect() -> Result<()> {
+        let sample = std::fs::read("assets/juventus.csv")?;
+        assert_eq!(
+            sniff(&sample),
+            Dialect {
+                delimiter: b',',
+                quote: b'"',
+                header: true,
+            }
+        );
+        let sample = b"name;note;kit\n'a;b';x, y;1\nc;d;2\ne;'f";
+        assert_eq!(
+            sniff(sample),
+            Dialect {
+                delimiter: b';',
+                quote: b'\'',
+                header: true,
+            }
+        );
+        assert!(!sniff(b"1\t2.5\ta\n3\t4\tb\n").header);
+        assert!(sniff(b"code|name\nAB1|x\nCD2|yy\n").header);
+        Ok(())
+    }
+}
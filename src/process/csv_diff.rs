@@ -0,0 +1,245 @@
+use std::collections::HashMap;
+
+use anyhow::{anyhow, Result};
+use colored::Colorize;
+use csv::StringRecord;
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+use super::{
+    csv_convert::{csv_reader, field},
+    csv_join::key_indices,
+};
+use crate::{cli::CsvReaderOpts, get_reader};
+
+#[derive(Debug, Default, Serialize)]
+pub struct CsvDiff {
+    pub columns_added: Vec<String>,
+    pub columns_removed: Vec<String>,
+    pub added: Vec<Map<String, Value>>,
+    pub removed: Vec<Map<String, Value>>,
+    pub changed: Vec<RowChange>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RowChange {
+    pub key: Vec<String>,
+    pub cells: Vec<CellChange>,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct CellChange {
+    pub column: String,
+    pub old: String,
+    pub new: String,
+}
+
+/// Compare two versions of a CSV file, matching rows by the key columns.
+/// Removed rows are listed in the order of the old file, added and changed
+/// rows in the order of the new one.
+pub fn process_csv_diff(
+    old: &str,
+    new: &str,
+    key: &[String],
+    reader_opts: &CsvReaderOpts,
+) -> Result<CsvDiff> {
+    let (old_headers, mut old_reader) = csv_reader(get_reader(old)?, reader_opts)?;
+    let (new_headers, mut new_reader) = csv_reader(get_reader(new)?, reader_opts)?;
+    let old_keys = key_indices(&old_headers, key, old)?;
+    let new_keys = key_indices(&new_headers, key, new)?;
+
+    let mut diff = CsvDiff {
+        columns_added: missing_from(&new_headers, &old_headers),
+        columns_removed: missing_from(&old_headers, &new_headers),
+        ..Default::default()
+    };
+    // columns present in both files, as (old index, new index)
+    let shared = old_headers
+        .iter()
+        .enumerate()
+        .filter_map(|(i, name)| Some((i, new_headers.iter().position(|h| h == name)?)))
+        .collect::<Vec<_>>();
+
+    let old_rows = old_reader.records().collect::<Result<Vec<_>, _>>()?;
+    let mut index: HashMap<Vec<&str>, usize> = HashMap::new();
+    for (i, row) in old_rows.iter().enumerate() {
+        if index.insert(row_key(row, &old_keys), i).is_some() {
+            return Err(duplicate_key(row, &old_keys, old));
+        }
+    }
+    let mut seen = vec![false; old_rows.len()];
+
+    for row in new_reader.records() {
+        let row = row?;
+        let Some(&i) = index.get(&row_key(&row, &new_keys)) else {
+            diff.added.push(to_map(&new_headers, &row));
+            continue;
+        };
+        if seen[i] {
+            return Err(duplicate_key(&row, &new_keys, new));
+        }
+        seen[i] = true;
+        let cells = shared
+            .iter()
+            .filter_map(|&(o, n)| {
+                let (old, new) = (field(&old_rows[i], o), field(&row, n));
+                (old != new).then(|| CellChange {
+                    column: old_headers[o].to_string(),
+                    old: old.to_string(),
+                    new: new.to_string(),
+                })
+            })
+            .collect::<Vec<_>>();
+        if !cells.is_empty() {
+            let key = row_key(&row, &new_keys).into_iter().map(String::from);
+            diff.changed.push(RowChange {
+                key: key.collect(),
+                cells,
+            });
+        }
+    }
+    diff.removed = old_rows
+        .iter()
+        .zip(seen)
+        .filter(|(_, seen)| !seen)
+        .map(|(row, _)| to_map(&old_headers, row))
+        .collect();
+    Ok(diff)
+}
+
+/// Render a diff as a colored report: `-` removed, `+` added, `~` changed rows.
+pub fn format_diff(diff: &CsvDiff, key: &[String]) -> String {
+    let mut ret = String::new();
+    for name in &diff.columns_removed {
+        ret.push_str(&format!("{}\n", format!("- column {:?}", name).red()));
+    }
+    for name in &diff.columns_added {
+        ret.push_str(&format!("{}\n", format!("+ column {:?}", name).green()));
+    }
+    let row_key = |row: &Map<String, Value>| {
+        key.iter()
+            .map(|k| row.get(k).and_then(Value::as_str).unwrap_or_default())
+            .collect::<Vec<_>>()
+            .join(",")
+    };
+    for row in &diff.removed {
+        let line = format!("- {}: {}", row_key(row), row_cells(row));
+        ret.push_str(&format!("{}\n", line.red()));
+    }
+    for row in &diff.added {
+        let line = format!("+ {}: {}", row_key(row), row_cells(row));
+        ret.push_str(&format!("{}\n", line.green()));
+    }
+    for change in &diff.changed {
+        ret.push_str(&format!(
+            "{}\n",
+            format!("~ {}", change.key.join(",")).yellow()
+        ));
+        for cell in &change.cells {
+            ret.push_str(&format!(
+                "    {}: {} -> {}\n",
+                cell.column,
+                cell.old.red(),
+                cell.new.green()
+            ));
+        }
+    }
+    ret.push_str(&format!(
+        "{} added, {} removed, {} changed\n",
+        diff.added.len(),
+        diff.removed.len(),
+        diff.changed.len()
+    ));
+    ret
+}
+
+fn row_cells(row: &Map<String, Value>) -> String {
+    row.iter()
+        .map(|(k, v)| format!("{}={}", k, v.as_str().unwrap_or_default()))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn to_map(headers: &StringRecord, row: &StringRecord) -> Map<String, Value> {
+    headers
+        .iter()
+        .enumerate()
+        .map(|(i, name)| (name.to_string(), Value::from(field(row, i))))
+        .collect()
+}
+
+fn missing_from(headers: &StringRecord, other: &StringRecord) -> Vec<String> {
+    headers
+        .iter()
+        .filter(|name| !other.iter().any(|h| h == *name))
+        .map(String::from)
+        .collect()
+}
+
+fn row_key<'a>(row: &'a StringRecord, indices: &[usize]) -> Vec<&'a str> {
+    indices.iter().map(|&i| field(row, i)).collect()
+}
+
+fn duplicate_key(row: &StringRecord, indices: &[usize], file: &str) -> anyhow::Error {
+    anyhow!(
+        "duplicate key {:?} in {}",
+        row_key(row, indices).join(","),
+        file
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::process::fixture;
+
+    #[test]
+    fn test_process_csv_diff() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let old = fixture(
+            &dir,
+            "old.csv",
+            "id,name,kit\n1,Buffon,1\n2,Dybala,21\n3,Pjanic,5\n",
+        )?;
+        let new = fixture(
+            &dir,
+            "new.csv",
+            "id,name,kit,club\n2,Dybala,10,Juventus\n1,Buffon,1,Juventus\n4,Ronaldo,7,Juventus\n",
+        )?;
+        let key = ["id".to_string()];
+        let diff = process_csv_diff(&old, &new, &key, &CsvReaderOpts::default())?;
+        assert_eq!(diff.columns_added, ["club"]);
+        assert!(diff.columns_removed.is_empty());
+        assert_eq!(diff.added.len(), 1);
+        assert_eq!(diff.added[0]["name"], "Ronaldo");
+        assert_eq!(diff.removed[0]["id"], "3");
+        assert_eq!(diff.changed.len(), 1);
+        assert_eq!(diff.changed[0].key, ["2"]);
+        assert_eq!(
+            diff.changed[0].cells,
+            [CellChange {
+                column: "kit".to_string(),
+                old: "21".to_string(),
+                new: "10".to_string(),
+            }]
+        );
+
+        colored::control::set_override(false);
+        let report = format_diff(&diff, &key);
+        assert!(report.contains("- 3: id=3, name=Pjanic, kit=5\n"));
+        assert!(report.contains("~ 2\n    kit: 21 -> 10\n"));
+        assert!(report.ends_with("1 added, 1 removed, 1 changed\n"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_diff_rejects_duplicate_keys() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let old = fixture(&dir, "dup.csv", "id,name\n1,a\n1,b\n")?;
+        let new = fixture(&dir, "dup-new.csv", "id,name\n1,a\n")?;
+        let err = process_csv_diff(&old, &new, &["id".to_string()], &CsvReaderOpts::default())
+            .unwrap_err();
+        assert!(err.to_string().starts_with("duplicate key \"1\""));
+        Ok(())
+    }
+}
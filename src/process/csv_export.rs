@@ -0,0 +1,257 @@
+use std::io::Write;
+
+use anyhow::{anyhow, Result};
+use csv::WriterBuilder;
+use serde_json::{Map, Value};
+
+use super::convert::read_records;
+use crate::{
+    cli::{CsvReaderOpts, OutputFormat},
+    get_reader,
+};
+
+pub fn process_to_csv(
+    input: &str,
+    output: impl Write,
+    from: OutputFormat,
+    csv_opts: &CsvReaderOpts,
+    toml_root: &str,
+) -> Result<()> {
+    let records = read_records(get_reader(input)?, from, csv_opts, true, toml_root)?;
+    write_csv(records, output, csv_opts)
+}
+
+/// Write records as CSV. The header is the union of all (flattened) keys in
+/// the order they are first seen.
+pub fn write_csv(records: Vec<Value>, writer: impl Write, opts: &CsvReaderOpts) -> Result<()> {
+    let rows = records
+        .into_iter()
+        .enumerate()
+        .map(|(i, record)| match record {
+            Value::Object(map) => {
+                let mut row = Map::new();
+                flatten_into(&mut row, None, map);
+                Ok(row)
+            }
+            _ => Err(anyhow!("record {} is not an object", i)),
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut headers: Vec<&String> = Vec::new();
+    for key in rows.iter().flat_map(|row| row.keys()) {
+        if !headers.contains(&key) {
+            headers.push(key);
+        }
+    }
+
+    let mut writer = WriterBuilder::new()
+        .delimiter(opts.delimiter)
+        .quote(opts.quote)
+        .from_writer(writer);
+    writer.write_record(&headers)?;
+    for row in &rows {
+        writer.write_record(
+            headers
+                .iter()
+                .map(|h| row.get(*h).map(cell).unwrap_or_default()),
+        )?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+// nested objects become dotted column names, e.g. `address.city`, and arrays
+// indexed ones, e.g. `tags[0]`
+fn flatten_into(row: &mut Map<String, Value>, prefix: Option<&str>, map: Map<String, Value>) {
+    for (key, value) in map {
+        let key = match prefix {
+            Some(prefix) => format!("{}.{}", prefix, key),
+            None => key,
+        };
+        flatten_value(row, key, value);
+    }
+}
+
+fn flatten_value(row: &mut Map<String, Value>, key: String, value: Value) {
+    match value {
+        Value::Object(map) => flatten_into(row, Some(&key), map),
+        Value::Array(items) => {
+            for (i, item) in items.into_iter().enumerate() {
+                flatten_value(row, format!("{}[{}]", key, i), item);
+            }
+        }
+        value => {
+            row.insert(key, value);
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Segment {
+    Key(String),
+    Index(usize),
+}
+
+/// Build nested objects and arrays from flat keys such as `address.city` or
+/// `tags[0]`. Keys that are not valid paths, e.g. `a..b`, are kept as they are.
+pub fn unflatten(record: Value) -> Result<Value> {
+    let Value::Object(map) = record else {
+        return Ok(record);
+    };
+    let mut ret = Value::Object(Map::new());
+    for (key, value) in map {
+        let path = parse_path(&key).unwrap_or_else(|| vec![Segment::Key(key.clone())]);
+        insert_path(&mut ret, &path, value)
+            .map_err(|_| anyhow!("column {:?} conflicts with another column", key))?;
+    }
+    Ok(ret)
+}
+
+fn parse_path(key: &str) -> Option<Vec<Segment>> {
+    let mut path = Vec::new();
+    for part in key.split('.') {
+        let (name, mut rest) = part.split_at(part.find('[').unwrap_or(part.len()));
+        if name.is_empty() {
+            return None;
+        }
+        path.push(Segment::Key(name.to_string()));
+        while !rest.is_empty() {
+            let end = rest.find(']')?;
+            path.push(Segment::Index(rest.get(1..end)?.parse().ok()?));
+            rest = &rest[end + 1..];
+            if !rest.is_empty() && !rest.starts_with('[') {
+                return None;
+            }
+        }
+    }
+    Some(path)
+}
+
+// errors when the path runs into a value of another shape
+fn insert_path(target: &mut Value, path: &[Segment], value: Value) -> Result<(), ()> {
+    let Some((segment, rest)) = path.split_first() else {
+        return match target {
+            Value::Null => {
+                *target = value;
+                Ok(())
+            }
+            _ => Err(()),
+        };
+    };
+    let empty = match rest.first() {
+        None => Value::Null,
+        Some(Segment::Key(_)) => Value::Object(Map::new()),
+        Some(Segment::Index(_)) => Value::Array(Vec::new()),
+    };
+    let child = match (segment, target) {
+        (Segment::Key(key), Value::Object(map)) => map.entry(key.clone()).or_insert(empty),
+        (Segment::Index(i), Value::Array(items)) => {
+            if items.len() <= *i {
+                items.resize(*i + 1, Value::Null);
+            }
+            if items[*i].is_null() {
+                items[*i] = empty;
+            }
+            &mut items[*i]
+        }
+        _ => return Err(()),
+    };
+    insert_path(child, rest, value)
+}
+
+fn cell(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(s) => s.clone(),
+        value => value.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn to_csv(records: Vec<Value>) -> Result<String> {
+        let mut buf = Vec::new();
+        write_csv(records, &mut buf, &CsvReaderOpts::default())?;
+        Ok(String::from_utf8(buf)?)
+    }
+
+    #[test]
+    fn test_write_csv_unions_and_flattens_keys() -> Result<()> {
+        let records = vec![
+            json!({"name": "a", "address": {"city": "Turin", "zip": "10100"}}),
+            json!({"name": "b", "kit": 7, "tags": ["x", "y"], "active": null}),
+        ];
+        let content = to_csv(records)?;
+        assert_eq!(
+            content,
+            "name,address.city,address.zip,kit,tags[0],tags[1],active\n\
+             a,Turin,10100,,,,\n\
+             b,,,7,x,y,\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_unflatten() -> Result<()> {
+        let flat = json!({
+            "name": "a",
+            "address.city": "Turin",
+            "address.zip": "10100",
+            "tags[1]": "y",
+            "tags[0]": "x",
+            "roles[0].team": "Juventus",
+            "roles[0].years[0]": 2015,
+            "Kit Number": 10,
+            "a..b": 1
+        });
+        let nested = json!({
+            "name": "a",
+            "address": {"city": "Turin", "zip": "10100"},
+            "tags": ["x", "y"],
+            "roles": [{"team": "Juventus", "years": [2015]}],
+            "Kit Number": 10,
+            "a..b": 1
+        });
+        assert_eq!(unflatten(flat)?, nested);
+
+        let mut row = Map::new();
+        flatten_value(&mut row, "r".into(), nested.clone());
+        let keys = row
+            .keys()
+            .map(|k| k.trim_start_matches("r."))
+            .collect::<Vec<_>>();
+        assert!(keys.contains(&"roles[0].years[0]"));
+        let row = row
+            .into_iter()
+            .map(|(k, v)| (k.trim_start_matches("r.").to_string(), v))
+            .collect();
+        assert_eq!(unflatten(Value::Object(row))?, nested);
+
+        assert!(unflatten(json!({"a": 1, "a.b": 2})).is_err());
+        assert!(unflatten(json!({"a[0]": 1, "a.b": 2})).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_juventus_round_trip() -> Result<()> {
+        let original = std::fs::read_to_string("assets/juventus.csv")?;
+        let mut reader = csv::Reader::from_reader(original.as_bytes());
+        let headers = reader.headers()?.clone();
+        let records = reader
+            .records()
+            .map(|r| Ok(headers.iter().zip(r?.iter()).collect::<Value>()))
+            .collect::<Result<Vec<_>>>()?;
+        let content = to_csv(records)?;
+
+        let parse = |s: &str| -> Result<Vec<csv::StringRecord>> {
+            Ok(csv::Reader::from_reader(s.as_bytes())
+                .records()
+                .collect::<Result<_, _>>()?)
+        };
+        assert_eq!(parse(&content)?, parse(&original)?);
+        Ok(())
+    }
+}
@@ -0,0 +1,235 @@
+use std::{collections::HashMap, io::Write};
+
+use anyhow::{anyhow, Result};
+use csv::{StringRecord, WriterBuilder};
+
+use super::csv_convert::{csv_reader, field};
+use crate::{
+    cli::{CsvReaderOpts, JoinKind},
+    get_reader,
+};
+
+/// Join two CSV files on key columns and write the result as CSV. The right
+/// file is held in memory while the left one is streamed.
+///
+/// The output has the left columns followed by the non-key right columns;
+/// right columns whose name is already taken get a `_right` suffix.
+pub fn process_csv_join(
+    left: &str,
+    right: &str,
+    on: &[String],
+    right_on: &[String],
+    how: JoinKind,
+    reader_opts: &CsvReaderOpts,
+    writer: impl Write,
+) -> Result<()> {
+    if on.len() != right_on.len() {
+        return Err(anyhow!(
+            "--on has {} columns but --right-on has {}",
+            on.len(),
+            right_on.len()
+        ));
+    }
+    let (left_headers, mut left_reader) = csv_reader(get_reader(left)?, reader_opts)?;
+    let (right_headers, mut right_reader) = csv_reader(get_reader(right)?, reader_opts)?;
+    let left_keys = key_indices(&left_headers, on, left)?;
+    let right_keys = key_indices(&right_headers, right_on, right)?;
+
+    let right_rows = right_reader.records().collect::<Result<Vec<_>, _>>()?;
+    let mut index: HashMap<Vec<&str>, Vec<usize>> = HashMap::new();
+    for (i, row) in right_rows.iter().enumerate() {
+        index.entry(key(row, &right_keys)).or_default().push(i);
+    }
+    let mut matched = vec![false; right_rows.len()];
+
+    // right columns that are not part of the key
+    let right_values = (0..right_headers.len())
+        .filter(|i| !right_keys.contains(i))
+        .collect::<Vec<_>>();
+    let mut headers = left_headers.iter().map(String::from).collect::<Vec<_>>();
+    for &i in &right_values {
+        let name = &right_headers[i];
+        if headers.iter().any(|h| h == name) {
+            headers.push(format!("{}_right", name));
+        } else {
+            headers.push(name.to_string());
+        }
+    }
+
+    let mut writer = WriterBuilder::new()
+        .delimiter(reader_opts.delimiter)
+        .quote(reader_opts.quote)
+        .from_writer(writer);
+    writer.write_record(&headers)?;
+
+    let keep_left = matches!(how, JoinKind::Left | JoinKind::Full);
+    let keep_right = matches!(how, JoinKind::Right | JoinKind::Full);
+    let cell = |row: &StringRecord, i: usize| field(row, i).to_string();
+    for row in left_reader.records() {
+        let row = row?;
+        let left_cells = (0..left_headers.len()).map(|i| cell(&row, i));
+        match index.get(&key(&row, &left_keys)) {
+            Some(matches) => {
+                for &m in matches {
+                    matched[m] = true;
+                    let right_cells = right_values.iter().map(|&i| cell(&right_rows[m], i));
+                    writer.write_record(left_cells.clone().chain(right_cells))?;
+                }
+            }
+            None if keep_left => {
+                let empty = right_values.iter().map(|_| String::new());
+                writer.write_record(left_cells.chain(empty))?;
+            }
+            None => {}
+        }
+    }
+
+    if keep_right {
+        for (row, _) in right_rows.iter().zip(&matched).filter(|(_, m)| !**m) {
+            // unmatched right rows still fill in the key columns on the left
+            let left_cells =
+                (0..left_headers.len()).map(|i| match left_keys.iter().position(|&k| k == i) {
+                    Some(k) => cell(row, right_keys[k]),
+                    None => String::new(),
+                });
+            let right_cells = right_values.iter().map(|&i| cell(row, i));
+            writer.write_record(left_cells.chain(right_cells))?;
+        }
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Append CSV files into one, writing the union of their columns in the order
+/// they are first seen and leaving cells empty where a file lacks a column.
+pub fn process_csv_concat(
+    inputs: &[String],
+    reader_opts: &CsvReaderOpts,
+    writer: impl Write,
+) -> Result<()> {
+    let readers = inputs
+        .iter()
+        .map(|input| csv_reader(get_reader(input)?, reader_opts))
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut headers: Vec<String> = Vec::new();
+    for (file_headers, _) in &readers {
+        for name in file_headers {
+            if !headers.iter().any(|h| h == name) {
+                headers.push(name.to_string());
+            }
+        }
+    }
+
+    let mut writer = WriterBuilder::new()
+        .delimiter(reader_opts.delimiter)
+        .quote(reader_opts.quote)
+        .from_writer(writer);
+    writer.write_record(&headers)?;
+    for (file_headers, mut reader) in readers {
+        // position of each output column in this file
+        let columns = headers
+            .iter()
+            .map(|h| file_headers.iter().position(|name| name == h))
+            .collect::<Vec<_>>();
+        for row in reader.records() {
+            let row = row?;
+            writer.write_record(
+                columns
+                    .iter()
+                    .map(|i| i.and_then(|i| row.get(i)).unwrap_or_default()),
+            )?;
+        }
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+pub(super) fn key_indices(
+    headers: &StringRecord,
+    names: &[String],
+    file: &str,
+) -> Result<Vec<usize>> {
+    names
+        .iter()
+        .map(|name| {
+            headers
+                .iter()
+                .position(|h| h == name)
+                .ok_or_else(|| anyhow!("key column {:?} not found in {}", name, file))
+        })
+        .collect()
+}
+
+fn key<'a>(row: &'a StringRecord, indices: &[usize]) -> Vec<&'a str> {
+    indices.iter().map(|&i| field(row, i)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::process::fixture;
+
+    fn join(how: JoinKind, right_on: &str) -> Result<String> {
+        let dir = tempfile::tempdir()?;
+        let left = fixture(
+            &dir,
+            "left.csv",
+            "id,name\n1,Buffon\n2,Dybala\n3,Chiellini\n",
+        )?;
+        let right = fixture(
+            &dir,
+            "right.csv",
+            "player,name,goals\n2,Paulo,10\n1,Gigi,0\n4,Ronaldo,28\n2,Paulo,11\n",
+        )?;
+        let mut buf = Vec::new();
+        process_csv_join(
+            &left,
+            &right,
+            &["id".to_string()],
+            &[right_on.to_string()],
+            how,
+            &CsvReaderOpts::default(),
+            &mut buf,
+        )?;
+        Ok(String::from_utf8(buf)?)
+    }
+
+    #[test]
+    fn test_join_kinds() -> Result<()> {
+        let header = "id,name,name_right,goals\n";
+        let inner = "1,Buffon,Gigi,0\n2,Dybala,Paulo,10\n2,Dybala,Paulo,11\n";
+        assert_eq!(
+            join(JoinKind::Inner, "player")?,
+            format!("{}{}", header, inner)
+        );
+        assert_eq!(
+            join(JoinKind::Left, "player")?,
+            format!("{}{}3,Chiellini,,\n", header, inner)
+        );
+        assert_eq!(
+            join(JoinKind::Right, "player")?,
+            format!("{}{}4,,Ronaldo,28\n", header, inner)
+        );
+        assert_eq!(
+            join(JoinKind::Full, "player")?,
+            format!("{}{}3,Chiellini,,\n4,,Ronaldo,28\n", header, inner)
+        );
+        assert!(join(JoinKind::Inner, "id").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_concat_fills_missing_columns() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let a = fixture(&dir, "a.csv", "name,kit\nBuffon,77\n")?;
+        let b = fixture(&dir, "b.csv", "kit,club,name\n10,Juventus,Dybala\n")?;
+        let mut buf = Vec::new();
+        process_csv_concat(&[a, b], &CsvReaderOpts::default(), &mut buf)?;
+        assert_eq!(
+            String::from_utf8(buf)?,
+            "name,kit,club\nBuffon,77,\nDybala,10,Juventus\n"
+        );
+        Ok(())
+    }
+}
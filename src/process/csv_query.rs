@@ -0,0 +1,591 @@
+use std::{cmp::Ordering, collections::HashSet};
+
+use anyhow::{anyhow, Result};
+use csv::StringRecord;
+use regex::Regex;
+use serde_json::{Map, Value};
+
+use crate::cli::CsvQueryOpts;
+
+/// `--select`, `--where`, `--distinct`, `--sort-by` and `--limit` applied to
+/// the record stream, in that order of evaluation:
+/// filter, dedupe, sort, limit and finally project.
+#[derive(Debug)]
+pub struct Query {
+    filter: Option<Expr>,
+    select: Option<Vec<String>>,
+    distinct: Option<Vec<String>>,
+    sort_by: Option<Vec<String>>,
+    desc: bool,
+    limit: Option<usize>,
+    seen: HashSet<String>,
+    held: Vec<Value>,
+    emitted: usize,
+}
+
+impl Query {
+    pub fn new(opts: &CsvQueryOpts, headers: &StringRecord) -> Result<Self> {
+        let filter = opts.filter.as_deref().map(Expr::parse).transpose()?;
+        let mut columns = Vec::new();
+        if let Some(expr) = &filter {
+            expr.columns(&mut columns);
+        }
+        for list in [&opts.select, &opts.distinct, &opts.sort_by]
+            .into_iter()
+            .flatten()
+        {
+            columns.extend(list.iter().map(String::as_str));
+        }
+        if let Some(name) = columns.iter().find(|c| !headers.iter().any(|h| h == **c)) {
+            return Err(anyhow!("unknown column {:?}", name));
+        }
+
+        Ok(Self {
+            filter,
+            select: opts.select.clone(),
+            distinct: opts.distinct.clone(),
+            sort_by: opts.sort_by.clone(),
+            desc: opts.desc,
+            limit: opts.limit,
+            seen: HashSet::new(),
+            held: Vec::new(),
+            emitted: 0,
+        })
+    }
+
+    /// Whether no further record can make it into the output.
+    pub fn is_done(&self) -> bool {
+        self.sort_by.is_none() && self.limit.is_some_and(|limit| self.emitted >= limit)
+    }
+
+    /// Run a record through the pipeline, returning it if it should be written
+    /// right away. Records are held back until `finish` when sorting.
+    pub fn push(&mut self, record: Value) -> Option<Value> {
+        if self.is_done() {
+            return None;
+        }
+        if let Some(expr) = &self.filter {
+            if !expr.eval(&record) {
+                return None;
+            }
+        }
+        if let Some(columns) = &self.distinct {
+            let key = columns
+                .iter()
+                .map(|c| field(&record, c))
+                .collect::<Vec<_>>();
+            if !self.seen.insert(Value::Array(key).to_string()) {
+                return None;
+            }
+        }
+        if self.sort_by.is_some() {
+            self.held.push(record);
+            return None;
+        }
+        self.emitted += 1;
+        Some(self.project(record))
+    }
+
+    /// The records held back for sorting, in their final order.
+    pub fn finish(mut self) -> Vec<Value> {
+        let Some(columns) = self.sort_by.take() else {
+            return Vec::new();
+        };
+        let mut held = std::mem::take(&mut self.held);
+        held.sort_by(|a, b| {
+            let ord = columns
+                .iter()
+                .map(|c| compare_values(&field(a, c), &field(b, c)))
+                .find(|ord| ord.is_ne())
+                .unwrap_or(Ordering::Equal);
+            if self.desc {
+                ord.reverse()
+            } else {
+                ord
+            }
+        });
+        held.truncate(self.limit.unwrap_or(usize::MAX));
+        held.into_iter().map(|r| self.project(r)).collect()
+    }
+
+    fn project(&self, record: Value) -> Value {
+        match &self.select {
+            Some(columns) => {
+                let map = columns
+                    .iter()
+                    .map(|c| (c.clone(), field(&record, c)))
+                    .collect::<Map<_, _>>();
+                Value::Object(map)
+            }
+            None => record,
+        }
+    }
+}
+
+fn field(record: &Value, column: &str) -> Value {
+    record.get(column).cloned().unwrap_or(Value::Null)
+}
+
+// nulls sort first, then bools, numbers and strings
+fn compare_values(a: &Value, b: &Value) -> Ordering {
+    fn rank(v: &Value) -> u8 {
+        match v {
+            Value::Null => 0,
+            Value::Bool(_) => 1,
+            Value::Number(_) => 2,
+            _ => 3,
+        }
+    }
+    match (a, b) {
+        (Value::Bool(a), Value::Bool(b)) => a.cmp(b),
+        (Value::Number(a), Value::Number(b)) => {
+            let (a, b) = (
+                a.as_f64().unwrap_or_default(),
+                b.as_f64().unwrap_or_default(),
+            );
+            a.total_cmp(&b)
+        }
+        (Value::String(a), Value::String(b)) => a.cmp(b),
+        _ if rank(a) != rank(b) => rank(a).cmp(&rank(b)),
+        _ => a.to_string().cmp(&b.to_string()),
+    }
+}
+
+/// A `--where` predicate, e.g. `Kit Number > 10 and (Position = Defender or Name ~ '^G')`.
+///
+/// Column names may contain spaces, or be quoted with backticks when they clash
+/// with a keyword. Values are numbers, quoted strings or bare words.
+#[derive(Debug)]
+pub enum Expr {
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Compare(String, Op, Literal),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Contains,
+    Matches,
+}
+
+#[derive(Debug)]
+pub struct Literal {
+    text: String,
+    // only set for unquoted values, so `'10'` is compared as a string
+    number: Option<f64>,
+    regex: Option<Regex>,
+}
+
+impl Expr {
+    pub fn parse(input: &str) -> Result<Self> {
+        let tokens = tokenize(input)?;
+        let mut parser = Parser {
+            input,
+            tokens,
+            pos: 0,
+        };
+        let expr = parser.or()?;
+        match parser.peek() {
+            None => Ok(expr),
+            Some(_) => Err(parser.error("expected `and`, `or` or end of expression")),
+        }
+    }
+
+    pub fn eval(&self, record: &Value) -> bool {
+        match self {
+            Expr::And(a, b) => a.eval(record) && b.eval(record),
+            Expr::Or(a, b) => a.eval(record) || b.eval(record),
+            Expr::Not(e) => !e.eval(record),
+            Expr::Compare(column, op, literal) => {
+                let value = record.get(column).unwrap_or(&Value::Null);
+                literal.test(value, *op)
+            }
+        }
+    }
+
+    fn columns<'a>(&'a self, out: &mut Vec<&'a str>) {
+        match self {
+            Expr::And(a, b) | Expr::Or(a, b) => {
+                a.columns(out);
+                b.columns(out);
+            }
+            Expr::Not(e) => e.columns(out),
+            Expr::Compare(column, _, _) => out.push(column),
+        }
+    }
+}
+
+impl Literal {
+    fn test(&self, value: &Value, op: Op) -> bool {
+        let text = match value {
+            Value::Null => String::new(),
+            Value::String(s) => s.clone(),
+            v => v.to_string(),
+        };
+        match op {
+            Op::Contains => return text.contains(&self.text),
+            Op::Matches => return self.regex.as_ref().is_some_and(|re| re.is_match(&text)),
+            _ => {}
+        }
+
+        let ord = if value.is_null() {
+            // `col = null` and `col = ''` both match empty cells
+            if self.text.eq_ignore_ascii_case("null") || self.text.is_empty() {
+                Some(Ordering::Equal)
+            } else {
+                None
+            }
+        } else {
+            let number = match value {
+                Value::Number(n) => n.as_f64(),
+                Value::String(s) => s.parse().ok(),
+                _ => None,
+            };
+            match (number, self.number) {
+                (Some(a), Some(b)) => a.partial_cmp(&b),
+                _ => Some(text.as_str().cmp(self.text.as_str())),
+            }
+        };
+        match (op, ord) {
+            (Op::Ne, ord) => ord != Some(Ordering::Equal),
+            (_, None) => false,
+            (Op::Eq, Some(ord)) => ord.is_eq(),
+            (Op::Lt, Some(ord)) => ord.is_lt(),
+            (Op::Le, Some(ord)) => ord.is_le(),
+            (Op::Gt, Some(ord)) => ord.is_gt(),
+            (Op::Ge, Some(ord)) => ord.is_ge(),
+            (Op::Contains | Op::Matches, _) => unreachable!(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    Quoted(String),
+    Column(String),
+    Op(Op),
+    And,
+    Or,
+    Not,
+    LParen,
+    RParen,
+}
+
+fn tokenize(input: &str) -> Result<Vec<(usize, Token)>> {
+    let mut tokens = Vec::new();
+    let mut chars = input.char_indices().peekable();
+    while let Some(&(pos, c)) = chars.peek() {
+        let token = match c {
+            c if c.is_whitespace() => {
+                chars.next();
+                continue;
+            }
+            '(' | ')' => {
+                chars.next();
+                if c == '(' {
+                    Token::LParen
+                } else {
+                    Token::RParen
+                }
+            }
+            '=' | '!' | '<' | '>' | '~' => {
+                chars.next();
+                let eq = chars.next_if(|&(_, c)| c == '=').is_some();
+                let op = match (c, eq) {
+                    ('=', _) => Op::Eq,
+                    ('!', true) => Op::Ne,
+                    ('<', false) => Op::Lt,
+                    ('<', true) => Op::Le,
+                    ('>', false) => Op::Gt,
+                    ('>', true) => Op::Ge,
+                    ('~', false) => Op::Matches,
+                    _ => return Err(syntax_error(input, pos, "unknown operator")),
+                };
+                Token::Op(op)
+            }
+            '\'' | '"' | '`' => {
+                chars.next();
+                let mut text = String::new();
+                loop {
+                    match chars.next() {
+                        Some((_, '\\')) => text.extend(chars.next().map(|(_, c)| c)),
+                        Some((_, q)) if q == c => break,
+                        Some((_, ch)) => text.push(ch),
+                        None => return Err(syntax_error(input, pos, "unterminated quote")),
+                    }
+                }
+                if c == '`' {
+                    Token::Column(text)
+                } else {
+                    Token::Quoted(text)
+                }
+            }
+            _ => {
+                let mut word = String::new();
+                while let Some((_, c)) =
+                    chars.next_if(|&(_, c)| !c.is_whitespace() && !"()=!<>~'\"`".contains(c))
+                {
+                    word.push(c);
+                }
+                match word.to_lowercase().as_str() {
+                    "and" => Token::And,
+                    "or" => Token::Or,
+                    "not" => Token::Not,
+                    "contains" => Token::Op(Op::Contains),
+                    "matches" => Token::Op(Op::Matches),
+                    _ => Token::Word(word),
+                }
+            }
+        };
+        tokens.push((pos, token));
+    }
+    Ok(tokens)
+}
+
+fn syntax_error(input: &str, pos: usize, msg: &str) -> anyhow::Error {
+    let col = input[..pos].chars().count();
+    anyhow!(
+        "invalid --where expression: {} at position {}\n  {}\n  {}^",
+        msg,
+        col,
+        input,
+        " ".repeat(col)
+    )
+}
+
+struct Parser<'a> {
+    input: &'a str,
+    tokens: Vec<(usize, Token)>,
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(_, t)| t)
+    }
+
+    fn error(&self, msg: &str) -> anyhow::Error {
+        let pos = self
+            .tokens
+            .get(self.pos)
+            .map_or(self.input.len(), |(pos, _)| *pos);
+        syntax_error(self.input, pos, msg)
+    }
+
+    fn or(&mut self) -> Result<Expr> {
+        let mut expr = self.and()?;
+        while self.peek() == Some(&Token::Or) {
+            self.pos += 1;
+            expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr> {
+        let mut expr = self.unary()?;
+        while self.peek() == Some(&Token::And) {
+            self.pos += 1;
+            expr = Expr::And(Box::new(expr), Box::new(self.unary()?));
+        }
+        Ok(expr)
+    }
+
+    fn unary(&mut self) -> Result<Expr> {
+        match self.peek() {
+            Some(Token::Not) => {
+                self.pos += 1;
+                Ok(Expr::Not(Box::new(self.unary()?)))
+            }
+            Some(Token::LParen) => {
+                self.pos += 1;
+                let expr = self.or()?;
+                if self.peek() != Some(&Token::RParen) {
+                    return Err(self.error("expected `)`"));
+                }
+                self.pos += 1;
+                Ok(expr)
+            }
+            _ => self.comparison(),
+        }
+    }
+
+    fn comparison(&mut self) -> Result<Expr> {
+        let column = match self.peek() {
+            Some(Token::Column(name)) => {
+                let name = name.clone();
+                self.pos += 1;
+                name
+            }
+            Some(Token::Word(_)) => self.words(),
+            _ => return Err(self.error("expected a column name")),
+        };
+        let op = match self.peek() {
+            Some(Token::Op(op)) => *op,
+            _ => return Err(self.error(&format!("expected an operator after {:?}", column))),
+        };
+        self.pos += 1;
+
+        let (text, quoted) = match self.peek() {
+            Some(Token::Quoted(text)) => {
+                let text = text.clone();
+                self.pos += 1;
+                (text, true)
+            }
+            Some(Token::Word(_)) => (self.words(), false),
+            _ => return Err(self.error("expected a value")),
+        };
+        let regex = match op {
+            Op::Matches => {
+                Some(Regex::new(&text).map_err(|e| anyhow!("invalid regex {:?}: {}", text, e))?)
+            }
+            _ => None,
+        };
+        let number = if quoted { None } else { text.parse().ok() };
+        Ok(Expr::Compare(
+            column,
+            op,
+            Literal {
+                text,
+                number,
+                regex,
+            },
+        ))
+    }
+
+    // consecutive bare words, joined by single spaces, e.g. `Kit Number`
+    fn words(&mut self) -> String {
+        let mut words = Vec::new();
+        while let Some(Token::Word(word)) = self.peek() {
+            words.push(word.clone());
+            self.pos += 1;
+        }
+        words.join(" ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn players() -> Vec<Value> {
+        vec![
+            json!({"Name": "Gianluigi Buffon", "Position": "Goalkeeper", "Kit Number": 77}),
+            json!({"Name": "Leonardo Bonucci", "Position": "Defender", "Kit Number": 19}),
+            json!({"Name": "Mattia De Sciglio", "Position": "Defender", "Kit Number": 2}),
+            json!({"Name": "Paulo Dybala", "Position": "Forward", "Kit Number": 10}),
+        ]
+    }
+
+    fn names(records: &[Value]) -> Vec<&str> {
+        records
+            .iter()
+            .map(|r| r["Name"].as_str().unwrap())
+            .collect()
+    }
+
+    fn filter(expr: &str) -> Result<Vec<Value>> {
+        let expr = Expr::parse(expr)?;
+        Ok(players().into_iter().filter(|r| expr.eval(r)).collect())
+    }
+
+    #[test]
+    fn test_where_expressions() -> Result<()> {
+        assert_eq!(
+            names(&filter("Kit Number > 10")?),
+            ["Gianluigi Buffon", "Leonardo Bonucci"]
+        );
+        assert_eq!(
+            names(&filter("Position = Defender and not `Kit Number` >= 10")?),
+            ["Mattia De Sciglio"]
+        );
+        assert_eq!(
+            names(&filter(
+                "(Name contains Dybala or Name ~ '^Gian') and Kit Number != 10"
+            )?),
+            ["Gianluigi Buffon"]
+        );
+        assert_eq!(
+            names(&filter("Name matches 'Bon+ucci$'")?),
+            ["Leonardo Bonucci"]
+        );
+        // quoted values compare as strings: "19" < "2"
+        assert_eq!(
+            names(&filter("Kit Number < '2'")?),
+            ["Leonardo Bonucci", "Paulo Dybala"]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_where_parse_errors() {
+        let err = Expr::parse("Kit Number 10").unwrap_err().to_string();
+        assert_eq!(
+            err,
+            "invalid --where expression: expected an operator after \"Kit Number 10\" at position 13\n  Kit Number 10\n               ^"
+        );
+        let err = Expr::parse("Name = 'x").unwrap_err().to_string();
+        assert!(err.contains("unterminated quote at position 7"));
+        let err = Expr::parse("(Name = x").unwrap_err().to_string();
+        assert!(err.contains("expected `)`"));
+        assert!(Expr::parse("Name ~ '('").is_err());
+        assert!(Expr::parse("Name = x y = z").is_err());
+    }
+
+    #[test]
+    fn test_query_pipeline() -> Result<()> {
+        let headers = StringRecord::from(vec!["Name", "Position", "Kit Number"]);
+        let opts = CsvQueryOpts {
+            select: Some(vec!["Name".into(), "Kit Number".into()]),
+            filter: Some("Kit Number > 1".into()),
+            distinct: Some(vec!["Position".into()]),
+            sort_by: Some(vec!["Kit Number".into()]),
+            desc: true,
+            limit: Some(2),
+        };
+        let mut query = Query::new(&opts, &headers)?;
+        for record in players() {
+            assert!(query.push(record).is_none());
+        }
+        assert_eq!(
+            query.finish(),
+            vec![
+                json!({"Name": "Gianluigi Buffon", "Kit Number": 77}),
+                json!({"Name": "Leonardo Bonucci", "Kit Number": 19}),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_query_streams_without_sort() -> Result<()> {
+        let headers = StringRecord::from(vec!["Name", "Position", "Kit Number"]);
+        let opts = CsvQueryOpts {
+            limit: Some(1),
+            filter: Some("Position = Defender".into()),
+            ..Default::default()
+        };
+        let mut query = Query::new(&opts, &headers)?;
+        let out = players()
+            .into_iter()
+            .filter_map(|r| query.push(r))
+            .collect::<Vec<_>>();
+        assert_eq!(names(&out), ["Leonardo Bonucci"]);
+        assert!(query.is_done());
+
+        let opts = CsvQueryOpts {
+            select: Some(vec!["Team".into()]),
+            ..Default::default()
+        };
+        assert!(Query::new(&opts, &headers).is_err());
+        Ok(())
+    }
+}
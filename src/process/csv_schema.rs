@@ -0,0 +1,590 @@
+use std::{collections::HashMap, fmt, fs, path::Path, str::FromStr};
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, NaiveDate, NaiveDateTime};
+use csv::StringRecord;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ColumnType {
+    Null,
+    Bool,
+    #[serde(alias = "int")]
+    Integer,
+    Float,
+    Date,
+    #[serde(alias = "str")]
+    String,
+}
+
+impl ColumnType {
+    /// Detect the narrowest type a single cell fits in.
+    pub fn detect(cell: &str) -> Self {
+        if cell.is_empty() {
+            ColumnType::Null
+        } else if parse_bool(cell).is_some() {
+            ColumnType::Bool
+        } else if parse_integer(cell).is_some() {
+            ColumnType::Integer
+        } else if is_integer_shaped(cell) {
+            // too big for i64, e.g. a card number; as a float it would lose digits
+            ColumnType::String
+        } else if parse_float(cell).is_some() {
+            ColumnType::Float
+        } else if is_date(cell) {
+            ColumnType::Date
+        } else {
+            ColumnType::String
+        }
+    }
+
+    /// Widen two types into one that can hold the values of both.
+    pub fn merge(self, other: Self) -> Self {
+        use ColumnType::*;
+        match (self, other) {
+            (Null, t) | (t, Null) => t,
+            (a, b) if a == b => a,
+            (Integer, Float) | (Float, Integer) => Float,
+            _ => String,
+        }
+    }
+
+    /// Convert a cell into a JSON value of this type. Empty cells are null
+    /// for every type but `String`.
+    pub fn parse(&self, cell: &str) -> Result<Value> {
+        if cell.is_empty() && *self != ColumnType::String {
+            return Ok(Value::Null);
+        }
+        let value = match self {
+            ColumnType::Null => None,
+            ColumnType::Bool => parse_bool(cell).map(Value::Bool),
+            ColumnType::Integer => parse_integer(cell).map(Value::from),
+            ColumnType::Float => parse_float(cell).map(Value::from),
+            ColumnType::Date => is_date(cell).then(|| Value::String(cell.to_string())),
+            ColumnType::String => Some(Value::String(cell.to_string())),
+        };
+        value.ok_or_else(|| anyhow!("cannot parse {:?} as {}", cell, self))
+    }
+}
+
+impl fmt::Display for ColumnType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ColumnType::Null => "null",
+            ColumnType::Bool => "bool",
+            ColumnType::Integer => "integer",
+            ColumnType::Float => "float",
+            ColumnType::Date => "date",
+            ColumnType::String => "string",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Constraints on a column, declared in a TOML column spec either as a bare type
+/// (`Name = "string"`) or a table:
+///
+/// ```toml
+/// "Kit Number" = { type = "integer", required = true, min = 1, max = 99 }
+/// Position = { enum = ["Goalkeeper", "Centre-Back"] }
+/// DOB = { pattern = '^\w{3} \d{1,2}, \d{4}' }
+/// ```
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ColumnSpec {
+    #[serde(rename = "type")]
+    pub column_type: Option<ColumnType>,
+    /// The column must exist and have no empty cells
+    #[serde(default)]
+    pub required: bool,
+    #[serde(rename = "enum")]
+    pub allowed: Option<Vec<Value>>,
+    pub pattern: Option<String>,
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+    #[serde(skip)]
+    regex: Option<Regex>,
+}
+
+impl ColumnSpec {
+    /// Check a cell against every constraint, returning one message per violation.
+    pub fn check(&self, cell: &str) -> Vec<String> {
+        let mut errors = Vec::new();
+        if cell.is_empty() {
+            if self.required {
+                errors.push("missing required value".to_string());
+            }
+            return errors;
+        }
+        if let Some(t) = self.column_type {
+            if let Err(e) = t.parse(cell) {
+                errors.push(e.to_string());
+            }
+        }
+        if let Some(allowed) = &self.allowed {
+            if !allowed.iter().any(|v| enum_matches(v, cell)) {
+                let allowed = allowed.iter().map(Value::to_string).collect::<Vec<_>>();
+                errors.push(format!("{:?} is not one of [{}]", cell, allowed.join(", ")));
+            }
+        }
+        if let Some(re) = &self.regex {
+            if !re.is_match(cell) {
+                errors.push(format!("{:?} does not match /{}/", cell, re));
+            }
+        }
+        if self.min.is_some() || self.max.is_some() {
+            match cell.parse::<f64>() {
+                Ok(n) if self.min.is_some_and(|min| n < min) => errors.push(format!(
+                    "{} is less than the minimum {}",
+                    cell,
+                    self.min.unwrap_or_default()
+                )),
+                Ok(n) if self.max.is_some_and(|max| n > max) => errors.push(format!(
+                    "{} is greater than the maximum {}",
+                    cell,
+                    self.max.unwrap_or_default()
+                )),
+                Ok(_) => {}
+                // a wrong type is already reported above
+                Err(_) if self.column_type.is_some() => {}
+                Err(_) => errors.push(format!("{:?} is not a number", cell)),
+            }
+        }
+        errors
+    }
+}
+
+fn enum_matches(value: &Value, cell: &str) -> bool {
+    match value {
+        Value::String(s) => s == cell,
+        Value::Number(n) => cell.parse::<f64>().ok() == n.as_f64(),
+        Value::Bool(b) => parse_bool(cell) == Some(*b),
+        _ => false,
+    }
+}
+
+/// Column types and constraints declared by the user, either as a TOML column
+/// spec or as a JSON Schema (`.json`) describing one row.
+#[derive(Debug, Default)]
+pub struct Schema {
+    columns: HashMap<String, ColumnSpec>,
+}
+
+impl Schema {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let content = fs::read_to_string(path)?;
+        if path.extension().is_some_and(|ext| ext == "json") {
+            Self::from_json_schema(&serde_json::from_str(&content)?)
+        } else {
+            content.parse()
+        }
+    }
+
+    /// Translate the subset of JSON Schema that maps onto column specs:
+    /// `properties` with `type`, `format: date`, `enum`, `pattern`,
+    /// `minimum`/`maximum`, and the `required` list.
+    pub fn from_json_schema(doc: &Value) -> Result<Self> {
+        let properties = doc
+            .get("properties")
+            .and_then(Value::as_object)
+            .ok_or_else(|| anyhow!("JSON schema has no \"properties\" object"))?;
+        let required = doc
+            .get("required")
+            .and_then(Value::as_array)
+            .map(|names| names.iter().filter_map(Value::as_str).collect::<Vec<_>>())
+            .unwrap_or_default();
+
+        let mut columns = HashMap::new();
+        for (name, prop) in properties {
+            let types = match prop.get("type") {
+                Some(Value::String(t)) => vec![t.as_str()],
+                Some(Value::Array(ts)) => ts.iter().filter_map(Value::as_str).collect(),
+                _ => vec![],
+            };
+            let format = prop.get("format").and_then(Value::as_str);
+            let column_type = match types.iter().find(|t| **t != "null") {
+                Some(&"integer") => Some(ColumnType::Integer),
+                Some(&"number") => Some(ColumnType::Float),
+                Some(&"boolean") => Some(ColumnType::Bool),
+                Some(&"string") if matches!(format, Some("date" | "date-time")) => {
+                    Some(ColumnType::Date)
+                }
+                Some(&"string") => Some(ColumnType::String),
+                Some(t) => return Err(anyhow!("unsupported type {:?} for column {:?}", t, name)),
+                None if types.contains(&"null") => Some(ColumnType::Null),
+                None => None,
+            };
+            let spec = ColumnSpec {
+                column_type,
+                required: required.contains(&name.as_str()),
+                allowed: prop.get("enum").and_then(Value::as_array).cloned(),
+                pattern: prop
+                    .get("pattern")
+                    .and_then(Value::as_str)
+                    .map(String::from),
+                min: prop.get("minimum").and_then(Value::as_f64),
+                max: prop.get("maximum").and_then(Value::as_f64),
+                regex: None,
+            };
+            columns.insert(name.clone(), spec);
+        }
+        Self::compile(columns)
+    }
+
+    fn compile(mut columns: HashMap<String, ColumnSpec>) -> Result<Self> {
+        for (name, spec) in columns.iter_mut() {
+            if let Some(pattern) = &spec.pattern {
+                let re = Regex::new(pattern)
+                    .map_err(|e| anyhow!("invalid pattern for column {:?}: {}", name, e))?;
+                spec.regex = Some(re);
+            }
+        }
+        Ok(Self { columns })
+    }
+
+    /// Specs of the columns found in `headers`, in header order.
+    pub fn specs<'a>(&'a self, headers: &StringRecord) -> Vec<Option<&'a ColumnSpec>> {
+        headers.iter().map(|name| self.columns.get(name)).collect()
+    }
+
+    /// Required columns missing from `headers`, sorted by name.
+    pub fn missing_columns(&self, headers: &StringRecord) -> Vec<&str> {
+        let mut missing = self
+            .columns
+            .iter()
+            .filter(|(name, spec)| spec.required && !headers.iter().any(|h| h == *name))
+            .map(|(name, _)| name.as_str())
+            .collect::<Vec<_>>();
+        missing.sort();
+        missing
+    }
+
+    /// Resolve the type of every column: pinned columns come from the schema,
+    /// the rest are inferred from the data or left as strings.
+    pub fn resolve(
+        &self,
+        headers: &StringRecord,
+        records: &[StringRecord],
+        infer: bool,
+    ) -> Result<ColumnTypes> {
+        if let Some(name) = self.missing_columns(headers).first() {
+            return Err(anyhow!("required column {:?} not found in headers", name));
+        }
+        let (types, pinned) = self
+            .specs(headers)
+            .into_iter()
+            .enumerate()
+            .map(|(i, spec)| match spec.and_then(|s| s.column_type) {
+                Some(t) => (t, true),
+                None if infer => (infer_column(records, i), false),
+                None => (ColumnType::String, false),
+            })
+            .unzip();
+        Ok(ColumnTypes { types, pinned })
+    }
+}
+
+impl FromStr for Schema {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let entries: toml::Table = toml::from_str(s)?;
+        let mut columns = HashMap::new();
+        // dispatched by hand rather than through an untagged enum, which would
+        // hide the actual error (e.g. an unknown key) behind "no variant matched"
+        for (name, entry) in entries {
+            let spec = parse_spec_entry(entry)
+                .map_err(|e| anyhow!("invalid spec for column {:?}: {}", name, e))?;
+            columns.insert(name, spec);
+        }
+        Self::compile(columns)
+    }
+}
+
+// a bare type such as `"integer"`, or a table of constraints
+fn parse_spec_entry(entry: toml::Value) -> Result<ColumnSpec> {
+    match entry {
+        toml::Value::String(_) => Ok(ColumnSpec {
+            column_type: Some(entry.try_into()?),
+            ..Default::default()
+        }),
+        toml::Value::Table(_) => Ok(entry.try_into()?),
+        _ => Err(anyhow!("expected a type name or a table")),
+    }
+}
+
+/// The resolved type of every column in a file.
+#[derive(Debug)]
+pub struct ColumnTypes {
+    types: Vec<ColumnType>,
+    pinned: Vec<bool>,
+}
+
+impl ColumnTypes {
+    /// Convert a record into a JSON object, failing on cells that don't parse.
+    pub fn to_value(&self, headers: &StringRecord, record: &StringRecord) -> Result<Value> {
+        self.convert(headers, record, false)
+    }
+
+    /// Like `to_value`, but cells of inferred columns that don't parse are kept
+    /// as strings. Used for records read after the inference sample.
+    pub fn to_value_lenient(&self, headers: &StringRecord, record: &StringRecord) -> Result<Value> {
+        self.convert(headers, record, true)
+    }
+
+    fn convert(
+        &self,
+        headers: &StringRecord,
+        record: &StringRecord,
+        lenient: bool,
+    ) -> Result<Value> {
+        let mut map = Map::with_capacity(headers.len());
+        for (i, (name, cell)) in headers.iter().zip(record.iter()).enumerate() {
+            let value = match self.types[i].parse(cell) {
+                Ok(value) => value,
+                Err(_) if lenient && !self.pinned[i] => Value::String(cell.to_string()),
+                Err(e) => {
+                    let line = record.position().map(|p| p.line()).unwrap_or_default();
+                    return Err(anyhow!("line {}, column {:?}: {}", line, name, e));
+                }
+            };
+            map.insert(name.to_string(), value);
+        }
+        Ok(Value::Object(map))
+    }
+}
+
+/// Read every record and convert it into a JSON object with typed values.
+#[cfg(test)]
+pub fn read_typed_records<R: std::io::Read>(
+    headers: &StringRecord,
+    records: csv::StringRecordsIter<R>,
+    infer: bool,
+    schema: &Schema,
+) -> Result<Vec<Value>> {
+    let records = records.collect::<Result<Vec<_>, _>>()?;
+    let types = schema.resolve(headers, &records, infer)?;
+    records
+        .iter()
+        .map(|record| types.to_value(headers, record))
+        .collect()
+}
+
+fn infer_column(records: &[StringRecord], index: usize) -> ColumnType {
+    records
+        .iter()
+        .filter_map(|r| r.get(index))
+        .map(ColumnType::detect)
+        .fold(ColumnType::Null, ColumnType::merge)
+}
+
+fn parse_bool(cell: &str) -> Option<bool> {
+    match cell.to_lowercase().as_str() {
+        "true" => Some(true),
+        "false" => Some(false),
+        _ => None,
+    }
+}
+
+// numbers with leading zeros (zip codes, ids) are kept as strings
+fn has_leading_zero(cell: &str) -> bool {
+    let digits = cell.trim_start_matches(['+', '-']);
+    digits.len() > 1 && digits.starts_with('0') && !digits[1..].starts_with('.')
+}
+
+fn is_integer_shaped(cell: &str) -> bool {
+    let digits = cell.strip_prefix(['+', '-']).unwrap_or(cell);
+    !digits.is_empty() && digits.bytes().all(|b| b.is_ascii_digit())
+}
+
+fn parse_integer(cell: &str) -> Option<i64> {
+    if has_leading_zero(cell) {
+        return None;
+    }
+    cell.parse().ok()
+}
+
+fn parse_float(cell: &str) -> Option<f64> {
+    if has_leading_zero(cell) {
+        return None;
+    }
+    cell.parse::<f64>().ok().filter(|f| f.is_finite())
+}
+
+fn is_date(cell: &str) -> bool {
+    NaiveDate::parse_from_str(cell, "%Y-%m-%d").is_ok()
+        || NaiveDateTime::parse_from_str(cell, "%Y-%m-%dT%H:%M:%S%.f").is_ok()
+        || DateTime::parse_from_rfc3339(cell).is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_detect_cell_type() {
+        assert_eq!(ColumnType::detect(""), ColumnType::Null);
+        assert_eq!(ColumnType::detect("TRUE"), ColumnType::Bool);
+        assert_eq!(ColumnType::detect("-42"), ColumnType::Integer);
+        assert_eq!(ColumnType::detect("0"), ColumnType::Integer);
+        assert_eq!(ColumnType::detect("0.5"), ColumnType::Float);
+        assert_eq!(ColumnType::detect("007"), ColumnType::String);
+        assert_eq!(
+            ColumnType::detect("9223372036854775807"),
+            ColumnType::Integer
+        );
+        assert_eq!(
+            ColumnType::detect("12345678901234567891"),
+            ColumnType::String
+        );
+        assert_eq!(
+            ColumnType::detect("-92233720368547758080"),
+            ColumnType::String
+        );
+        assert_eq!(ColumnType::detect("1e25"), ColumnType::Float);
+        assert_eq!(ColumnType::detect("inf"), ColumnType::String);
+        assert_eq!(ColumnType::detect("2024-02-29"), ColumnType::Date);
+        assert_eq!(ColumnType::detect("2024-02-30"), ColumnType::String);
+        assert_eq!(ColumnType::detect("2024-02-29T10:00:00Z"), ColumnType::Date);
+        assert_eq!(ColumnType::detect("Apr 18, 1990 (29)"), ColumnType::String);
+    }
+
+    #[test]
+    fn test_merge_column_type() {
+        use ColumnType::*;
+        assert_eq!(Null.merge(Integer), Integer);
+        assert_eq!(Integer.merge(Float), Float);
+        assert_eq!(Bool.merge(Integer), String);
+        assert_eq!(Date.merge(Null), Date);
+    }
+
+    #[test]
+    fn test_read_typed_records() -> Result<()> {
+        let data = "name,kit,ratio,active,joined\na,1,0.5,true,2020-01-01\nb,,1,false,\n";
+        let mut reader = csv::Reader::from_reader(data.as_bytes());
+        let headers = reader.headers()?.clone();
+        let ret = read_typed_records(&headers, reader.records(), true, &Schema::default())?;
+        assert_eq!(
+            ret[0],
+            json!({"name": "a", "kit": 1, "ratio": 0.5, "active": true, "joined": "2020-01-01"})
+        );
+        assert_eq!(
+            ret[1],
+            json!({"name": "b", "kit": null, "ratio": 1.0, "active": false, "joined": null})
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_schema_reports_bad_cell() -> Result<()> {
+        let schema: Schema = r#"kit = "integer""#.parse()?;
+        let data = "name,kit\na,1\nb,x\n";
+        let mut reader = csv::Reader::from_reader(data.as_bytes());
+        let headers = reader.headers()?.clone();
+        let err = read_typed_records(&headers, reader.records(), false, &schema).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            r#"line 3, column "kit": cannot parse "x" as integer"#
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_lenient_keeps_unparsed_inferred_cells() -> Result<()> {
+        let schema: Schema = r#"kit = "integer""#.parse()?;
+        let data = "kit,score\n1,2\n3,n/a\n";
+        let mut reader = csv::Reader::from_reader(data.as_bytes());
+        let headers = reader.headers()?.clone();
+        let records = reader.records().collect::<Result<Vec<_>, _>>()?;
+        let types = schema.resolve(&headers, &records[..1], true)?;
+        assert_eq!(
+            types.to_value_lenient(&headers, &records[1])?,
+            json!({"kit": 3, "score": "n/a"})
+        );
+        assert!(types.to_value(&headers, &records[1]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_column_spec_check() -> Result<()> {
+        let schema: Schema = r#"
+            kit = { type = "integer", required = true, min = 1, max = 99 }
+            position = { enum = ["Goalkeeper", "Defender"] }
+            dob = { pattern = '^\d{4}-\d{2}-\d{2}$' }
+            rating = { min = 0 }
+        "#
+        .parse()?;
+        let headers = StringRecord::from(vec!["kit", "position", "dob", "rating", "name"]);
+        let specs = schema.specs(&headers);
+        let kit = specs[0].unwrap();
+        assert!(kit.check("10").is_empty());
+        assert_eq!(kit.check(""), ["missing required value"]);
+        assert_eq!(kit.check("x"), [r#"cannot parse "x" as integer"#]);
+        assert_eq!(kit.check("100"), ["100 is greater than the maximum 99"]);
+        assert_eq!(
+            specs[1].unwrap().check("Striker"),
+            [r#""Striker" is not one of ["Goalkeeper", "Defender"]"#]
+        );
+        assert!(specs[1].unwrap().check("").is_empty());
+        assert_eq!(
+            specs[2].unwrap().check("1990"),
+            [r#""1990" does not match /^\d{4}-\d{2}-\d{2}$/"#]
+        );
+        assert_eq!(specs[3].unwrap().check("n/a"), [r#""n/a" is not a number"#]);
+        assert!(specs[4].is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_schema_from_json_schema() -> Result<()> {
+        let doc = json!({
+            "type": "object",
+            "required": ["Name", "Team"],
+            "properties": {
+                "Name": {"type": "string"},
+                "Team": {"type": "string"},
+                "Kit Number": {"type": ["integer", "null"], "minimum": 1},
+                "DOB": {"type": "string", "format": "date"},
+                "Position": {"enum": ["Goalkeeper", 1]}
+            }
+        });
+        let schema = Schema::from_json_schema(&doc)?;
+        let headers = StringRecord::from(vec!["Name", "Kit Number", "DOB", "Position"]);
+        assert_eq!(schema.missing_columns(&headers), ["Team"]);
+        let specs = schema.specs(&headers);
+        assert!(specs[0].unwrap().required);
+        assert_eq!(specs[1].unwrap().column_type, Some(ColumnType::Integer));
+        assert_eq!(
+            specs[1].unwrap().check("0"),
+            ["0 is less than the minimum 1"]
+        );
+        assert_eq!(specs[2].unwrap().column_type, Some(ColumnType::Date));
+        assert!(specs[3].unwrap().check("1").is_empty());
+        assert!(Schema::from_json_schema(&json!({"type": "object"})).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_schema_rejects_bad_spec() {
+        let err = r#"kit = { type = "integer", requird = true }"#.parse::<Schema>().unwrap_err();
+        assert!(err.to_string().contains("column \"kit\""), "{}", err);
+        assert!(
+            err.to_string().contains("unknown field `requird`"),
+            "{}",
+            err
+        );
+        let err = r#"kit = "int64""#.parse::<Schema>().unwrap_err();
+        assert!(
+            err.to_string().contains("unknown variant `int64`"),
+            "{}",
+            err
+        );
+        assert!("kit = 1".parse::<Schema>().is_err());
+        assert!(r#"kit = { pattern = "(" }"#.parse::<Schema>().is_err());
+    }
+}
@@ -0,0 +1,197 @@
+use std::collections::VecDeque;
+
+use anyhow::Result;
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+use super::{csv_convert::csv_reader, csv_schema::ColumnType};
+use crate::{
+    cli::{CsvReaderOpts, RowWindow},
+    get_reader,
+};
+
+pub fn process_csv_show(
+    input: &str,
+    reader_opts: &CsvReaderOpts,
+    window: RowWindow,
+    max_width: usize,
+    row_numbers: bool,
+) -> Result<String> {
+    let (headers, mut reader) = csv_reader(get_reader(input)?, reader_opts)?;
+
+    // (1-based row number, cells) of the rows to display
+    let mut rows: VecDeque<(usize, Vec<String>)> = VecDeque::new();
+    for (i, record) in reader.records().enumerate() {
+        let n = i + 1;
+        match window {
+            RowWindow::Head(count) if n > count => break,
+            RowWindow::Range(_, end) if n > end => break,
+            RowWindow::Range(start, _) if n < start => continue,
+            _ => {}
+        }
+        rows.push_back((n, record?.iter().map(String::from).collect()));
+        if let RowWindow::Tail(count) = window {
+            if rows.len() > count {
+                rows.pop_front();
+            }
+        }
+    }
+
+    let mut headers = headers.iter().map(String::from).collect::<Vec<_>>();
+    let mut rows = rows
+        .into_iter()
+        .map(|(n, mut cells)| {
+            if row_numbers {
+                cells.insert(0, n.to_string());
+            }
+            cells
+        })
+        .collect::<Vec<_>>();
+    if row_numbers {
+        headers.insert(0, "#".to_string());
+    }
+    for row in &mut rows {
+        row.resize(headers.len(), String::new());
+    }
+    Ok(render_table(&headers, &rows, max_width))
+}
+
+/// Render rows as a box-drawn table. Cells wider than `max_width` are cut and
+/// end with `…`; numeric columns are right aligned.
+pub fn render_table(headers: &[String], rows: &[Vec<String>], max_width: usize) -> String {
+    let max_width = max_width.max(1);
+    let cells = rows
+        .iter()
+        .map(|row| {
+            row.iter()
+                .map(|c| truncate(c, max_width))
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+    let headers = headers
+        .iter()
+        .map(|h| truncate(h, max_width))
+        .collect::<Vec<_>>();
+
+    let mut widths = headers.iter().map(|h| h.width()).collect::<Vec<_>>();
+    for row in &cells {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.width());
+        }
+    }
+    let numeric = (0..headers.len())
+        .map(|i| {
+            let t = rows
+                .iter()
+                .filter_map(|row| row.get(i))
+                .map(|c| ColumnType::detect(c))
+                .fold(ColumnType::Null, ColumnType::merge);
+            matches!(t, ColumnType::Integer | ColumnType::Float)
+        })
+        .collect::<Vec<_>>();
+
+    let border = |left: &str, mid: &str, right: &str| {
+        let line = widths
+            .iter()
+            .map(|w| "─".repeat(w + 2))
+            .collect::<Vec<_>>()
+            .join(mid);
+        format!("{}{}{}\n", left, line, right)
+    };
+    let line = |row: &[String], align_right: &[bool]| {
+        let cells = row
+            .iter()
+            .zip(&widths)
+            .zip(align_right)
+            .map(|((cell, width), right)| {
+                let pad = " ".repeat(width - cell.width());
+                if *right {
+                    format!(" {}{} ", pad, cell)
+                } else {
+                    format!(" {}{} ", cell, pad)
+                }
+            })
+            .collect::<Vec<_>>()
+            .join("│");
+        format!("│{}│\n", cells)
+    };
+
+    let mut ret = border("┌", "┬", "┐");
+    ret.push_str(&line(&headers, &vec![false; headers.len()]));
+    ret.push_str(&border("├", "┼", "┤"));
+    for row in &cells {
+        ret.push_str(&line(row, &numeric));
+    }
+    ret.push_str(&border("└", "┴", "┘"));
+    ret
+}
+
+// fit a cell into `max_width` terminal columns, flattening line breaks
+fn truncate(cell: &str, max_width: usize) -> String {
+    let cell = cell.replace(['\r', '\n', '\t'], " ");
+    if cell.width() <= max_width {
+        return cell;
+    }
+    let mut ret = String::new();
+    let mut width = 0;
+    for c in cell.chars() {
+        let w = c.width().unwrap_or(0);
+        if width + w > max_width - 1 {
+            break;
+        }
+        width += w;
+        ret.push(c);
+    }
+    ret.push('…');
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_table() {
+        let headers = vec!["Name".to_string(), "Kit".to_string()];
+        let rows = vec![
+            vec!["Gianluigi Buffon".to_string(), "77".to_string()],
+            vec!["Dybala".to_string(), "10".to_string()],
+        ];
+        let expected = "\
+┌─────────┬─────┐
+│ Name    │ Kit │
+├─────────┼─────┤
+│ Gianlu… │  77 │
+│ Dybala  │  10 │
+└─────────┴─────┘
+";
+        assert_eq!(render_table(&headers, &rows, 7), expected);
+    }
+
+    #[test]
+    fn test_truncate_wide_chars() {
+        assert_eq!(truncate("尤文图斯", 5), "尤文…");
+        assert_eq!(truncate("a\nb", 5), "a b");
+    }
+
+    #[test]
+    fn test_process_csv_show_windows() -> Result<()> {
+        let opts = CsvReaderOpts::default();
+        let show = |window| process_csv_show("assets/juventus.csv", &opts, window, 40, true);
+
+        let table = show(RowWindow::Head(2))?;
+        assert_eq!(table.lines().count(), 6);
+        assert!(table.contains("│ 2 │ Mattia Perin"));
+
+        let table = show(RowWindow::Tail(1))?;
+        assert_eq!(table.lines().count(), 5);
+        assert!(table.contains("│ 27 │"));
+
+        let table = show(RowWindow::Range(3, 4))?;
+        let rows = table.lines().filter(|l| l.starts_with("│ ")).skip(1);
+        let numbers = rows
+            .map(|l| l.split('│').nth(1).unwrap().trim().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(numbers, ["3", "4"]);
+        Ok(())
+    }
+}
@@ -0,0 +1,268 @@
+use std::{cmp::Ordering, collections::HashMap};
+
+use anyhow::Result;
+use serde::Serialize;
+use serde_json::Value;
+
+use super::{
+    csv_convert::{csv_reader, field},
+    csv_schema::ColumnType,
+};
+use crate::{cli::CsvReaderOpts, get_reader};
+
+#[derive(Debug, Serialize)]
+pub struct ColumnStats {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub column_type: ColumnType,
+    pub nulls: usize,
+    pub distinct: usize,
+    pub min: Option<Value>,
+    pub max: Option<Value>,
+    pub mean: Option<f64>,
+    pub median: Option<f64>,
+    pub max_length: usize,
+    pub top: Vec<ValueCount>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ValueCount {
+    pub value: String,
+    pub count: usize,
+}
+
+pub fn process_csv_stats(
+    input: &str,
+    reader_opts: &CsvReaderOpts,
+    top: usize,
+) -> Result<Vec<ColumnStats>> {
+    let (headers, mut reader) = csv_reader(get_reader(input)?, reader_opts)?;
+    let mut columns = vec![Accumulator::default(); headers.len()];
+    for record in reader.records() {
+        let record = record?;
+        for (i, column) in columns.iter_mut().enumerate() {
+            column.push(field(&record, i));
+        }
+    }
+    Ok(headers
+        .iter()
+        .zip(columns)
+        .map(|(name, column)| column.finish(name, top))
+        .collect())
+}
+
+/// Render stats as an aligned plain-text table, one row per column.
+pub fn format_stats_table(stats: &[ColumnStats]) -> String {
+    let header = [
+        "column", "type", "nulls", "distinct", "min", "max", "mean", "median", "max len", "top",
+    ];
+    let rows = stats
+        .iter()
+        .map(|s| {
+            let top = s
+                .top
+                .iter()
+                .map(|vc| format!("{} ({})", vc.value, vc.count))
+                .collect::<Vec<_>>()
+                .join(", ");
+            vec![
+                s.name.clone(),
+                s.column_type.to_string(),
+                s.nulls.to_string(),
+                s.distinct.to_string(),
+                s.min.as_ref().map(cell).unwrap_or_default(),
+                s.max.as_ref().map(cell).unwrap_or_default(),
+                s.mean.map(|v| format!("{:.2}", v)).unwrap_or_default(),
+                s.median.map(|v| format!("{:.2}", v)).unwrap_or_default(),
+                s.max_length.to_string(),
+                top,
+            ]
+        })
+        .collect::<Vec<_>>();
+
+    let mut widths = header.map(str::len);
+    for row in &rows {
+        for (width, value) in widths.iter_mut().zip(row) {
+            *width = (*width).max(value.chars().count());
+        }
+    }
+    let mut ret = String::new();
+    let header = header.map(String::from).to_vec();
+    for row in std::iter::once(&header).chain(&rows) {
+        let line = row
+            .iter()
+            .zip(widths)
+            .map(|(value, width)| format!("{:<width$}", value, width = width))
+            .collect::<Vec<_>>()
+            .join("  ");
+        ret.push_str(line.trim_end());
+        ret.push('\n');
+    }
+    ret
+}
+
+fn cell(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        v => v.to_string(),
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Accumulator {
+    column_type: ColumnType,
+    nulls: usize,
+    counts: HashMap<String, usize>,
+    max_length: usize,
+}
+
+impl Default for Accumulator {
+    fn default() -> Self {
+        Self {
+            column_type: ColumnType::Null,
+            nulls: 0,
+            counts: HashMap::new(),
+            max_length: 0,
+        }
+    }
+}
+
+impl Accumulator {
+    fn push(&mut self, cell: &str) {
+        if cell.is_empty() {
+            self.nulls += 1;
+            return;
+        }
+        self.column_type = self.column_type.merge(ColumnType::detect(cell));
+        self.max_length = self.max_length.max(cell.chars().count());
+        *self.counts.entry(cell.to_string()).or_default() += 1;
+    }
+
+    fn finish(self, name: &str, top: usize) -> ColumnStats {
+        let numeric = matches!(self.column_type, ColumnType::Integer | ColumnType::Float);
+        let compare = |a: &&String, b: &&String| -> Ordering {
+            match (numeric, a.parse::<f64>(), b.parse::<f64>()) {
+                (true, Ok(a), Ok(b)) => a.total_cmp(&b),
+                _ => a.cmp(b),
+            }
+        };
+        let typed = |s: &String| self.column_type.parse(s).ok();
+        let min = self.counts.keys().min_by(compare).and_then(typed);
+        let max = self.counts.keys().max_by(compare).and_then(typed);
+
+        let (mean, median) = if numeric {
+            let mut values = self
+                .counts
+                .iter()
+                .filter_map(|(v, c)| Some((v.parse::<f64>().ok()?, *c)))
+                .collect::<Vec<_>>();
+            values.sort_by(|a, b| a.0.total_cmp(&b.0));
+            (mean(&values), median(&values))
+        } else {
+            (None, None)
+        };
+
+        let mut frequent = self.counts.iter().collect::<Vec<_>>();
+        frequent.sort_by(|a, b| b.1.cmp(a.1).then_with(|| a.0.cmp(b.0)));
+        let top = frequent
+            .into_iter()
+            .take(top)
+            .map(|(value, count)| ValueCount {
+                value: value.clone(),
+                count: *count,
+            })
+            .collect();
+
+        ColumnStats {
+            name: name.to_string(),
+            column_type: self.column_type,
+            nulls: self.nulls,
+            distinct: self.counts.len(),
+            min,
+            max,
+            mean,
+            median,
+            max_length: self.max_length,
+            top,
+        }
+    }
+}
+
+// `values` are (value, count) pairs sorted by value
+fn mean(values: &[(f64, usize)]) -> Option<f64> {
+    let n = values.iter().map(|(_, c)| c).sum::<usize>();
+    let sum = values.iter().map(|(v, c)| v * *c as f64).sum::<f64>();
+    (n > 0).then(|| sum / n as f64)
+}
+
+fn median(values: &[(f64, usize)]) -> Option<f64> {
+    let n = values.iter().map(|(_, c)| c).sum::<usize>();
+    // the value at a 0-based position in the expanded, sorted list
+    let nth = |pos: usize| {
+        let mut seen = 0;
+        values.iter().find_map(|(v, c)| {
+            seen += c;
+            (seen > pos).then_some(*v)
+        })
+    };
+    match n {
+        0 => None,
+        n if n % 2 == 1 => nth(n / 2),
+        n => Some((nth(n / 2 - 1)? + nth(n / 2)?) / 2.0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_process_csv_stats() -> Result<()> {
+        let stats = process_csv_stats("assets/juventus.csv", &CsvReaderOpts::default(), 1)?;
+        assert_eq!(stats.len(), 5);
+
+        let kit = &stats[4];
+        assert_eq!(kit.name, "Kit Number");
+        assert_eq!(kit.column_type, ColumnType::Integer);
+        assert_eq!(kit.nulls, 0);
+        assert_eq!(kit.min, Some(json!(1)));
+        assert_eq!(kit.max, Some(json!(77)));
+        assert!(kit.mean.is_some() && kit.median.is_some());
+
+        let nationality = &stats[3];
+        assert_eq!(nationality.column_type, ColumnType::String);
+        assert_eq!(nationality.top[0].value, "Italy");
+        assert_eq!(nationality.mean, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_mean_and_median() {
+        let values = [(1.0, 1), (2.0, 2), (10.0, 1)];
+        assert_eq!(mean(&values), Some(3.75));
+        assert_eq!(median(&values), Some(2.0));
+        assert_eq!(median(&[(1.0, 1), (4.0, 1)]), Some(2.5));
+        assert_eq!(median(&[]), None);
+    }
+
+    #[test]
+    fn test_accumulator() {
+        let mut acc = Accumulator::default();
+        for cell in ["3", "", "1.5", "3", "10"] {
+            acc.push(cell);
+        }
+        let stats = acc.finish("n", 1);
+        assert_eq!(stats.column_type, ColumnType::Float);
+        assert_eq!(stats.nulls, 1);
+        assert_eq!(stats.distinct, 3);
+        assert_eq!(stats.min, Some(json!(1.5)));
+        assert_eq!(stats.max, Some(json!(10.0)));
+        assert_eq!(stats.median, Some(3.0));
+        assert_eq!(stats.top[0].value, "3");
+        assert_eq!(stats.max_length, 3);
+
+        let table = format_stats_table(&[stats]);
+        assert!(table.starts_with("column  type   nulls"));
+    }
+}
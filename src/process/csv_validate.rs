@@ -0,0 +1,109 @@
+use anyhow::Result;
+use serde::Serialize;
+
+use super::{
+    csv_convert::{csv_reader, field},
+    csv_schema::Schema,
+};
+use crate::{cli::CsvReaderOpts, get_reader};
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Violation {
+    pub line: u64,
+    pub column: String,
+    pub message: String,
+}
+
+/// Check every row of a CSV file against a schema, collecting all violations
+/// instead of stopping at the first one.
+pub fn process_csv_validate(
+    input: &str,
+    reader_opts: &CsvReaderOpts,
+    schema: &str,
+) -> Result<Vec<Violation>> {
+    let schema = Schema::load(schema)?;
+    let (headers, mut reader) = csv_reader(get_reader(input)?, reader_opts)?;
+
+    // missing columns are reported against the header line
+    let mut violations = schema
+        .missing_columns(&headers)
+        .into_iter()
+        .map(|name| Violation {
+            line: 1,
+            column: name.to_string(),
+            message: "missing required column".to_string(),
+        })
+        .collect::<Vec<_>>();
+
+    let specs = schema.specs(&headers);
+    for record in reader.records() {
+        let record = record?;
+        let line = record.position().map(|p| p.line()).unwrap_or_default();
+        for (i, (name, spec)) in headers.iter().zip(&specs).enumerate() {
+            let Some(spec) = spec else { continue };
+            for message in spec.check(field(&record, i)) {
+                violations.push(Violation {
+                    line,
+                    column: name.to_string(),
+                    message,
+                });
+            }
+        }
+    }
+    Ok(violations)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_process_csv_validate() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let schema = dir.path().join("players.schema.toml");
+        fs::write(
+            &schema,
+            r#"
+Name = { type = "string", required = true }
+Club = { required = true }
+"Kit Number" = { type = "integer", required = true, min = 1, max = 50 }
+"#,
+        )?;
+        let input = dir.path().join("players.csv");
+        fs::write(&input, "Name,Kit Number\nDybala,10\nVlahovic,77\n,x\n")?;
+
+        let violations = process_csv_validate(
+            input.to_str().unwrap(),
+            &CsvReaderOpts::default(),
+            schema.to_str().unwrap(),
+        )?;
+        let violation = |line, column: &str, message: &str| Violation {
+            line,
+            column: column.to_string(),
+            message: message.to_string(),
+        };
+        assert_eq!(
+            violations[0],
+            violation(1, "Club", "missing required column")
+        );
+        assert_eq!(
+            violations[1],
+            violation(3, "Kit Number", "77 is greater than the maximum 50")
+        );
+        assert!(violations[2..].iter().all(|v| v.line == 4));
+        assert!(violations.iter().any(|v| v.column == "Name"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_juventus_schema() -> Result<()> {
+        let violations = process_csv_validate(
+            "assets/juventus.csv",
+            &CsvReaderOpts::default(),
+            "assets/juventus.schema.toml",
+        )?;
+        assert_eq!(violations, []);
+        Ok(())
+    }
+}
@@ -1,48 +1,561 @@
-use anyhow::Result;
-use rand::{seq::SliceRandom, Rng};
-use zxcvbn::zxcvbn;
+use std::{iter::Peekable, str::Chars, str::FromStr};
 
-const UPPER: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ";
-const LOWER: &[u8] = b"abcdefghijklmnopqrstuvwxyz";
-const NUMBER: &[u8] = b"0123456789";
-const SYMBOL: &[u8] = b"`~!@#$%^&*()-_=+[{]}\\|;:'\",<.>/?";
+use anyhow::{anyhow, Result};
+use rand::{seq::SliceRandom, CryptoRng, Rng};
+use serde::Serialize;
+use zxcvbn::{zxcvbn, Entropy};
 
-// TODO: to fix error: unexpected argument 'false' found
+const UPPER: &str = "ABCDEFGHIJKLMNOPQRSTUVWXYZ";
+const LOWER: &str = "abcdefghijklmnopqrstuvwxyz";
+const NUMBER: &str = "0123456789";
+pub const SYMBOL: &str = "`~!@#$%^&*()-_=+[{]}\\|;:'\",<.>/?";
+const VOWEL: &str = "aeiou";
+const CONSONANT: &str = "bcdfghjklmnpqrstvwxyz";
+// characters easily confused with one another: 0/O and l/1/I
+const AMBIGUOUS: &str = "0O1lI";
+
+// give up on policies that (almost) no password can satisfy
+pub(super) const MAX_ATTEMPTS: usize = 1000;
+
+// the longest password a pattern may expand to, the same limit as `--length`
+const MAX_PATTERN_LEN: usize = u16::MAX as usize;
+
+/// A generated password or passphrase with its strength estimate.
+#[derive(Debug, Clone, Serialize)]
+pub struct GeneratedPassword {
+    pub password: String,
+    /// zxcvbn score, 0 to 4
+    pub score: u8,
+    /// Time to crack offline against a slow hash, e.g. `3 hours`
+    pub crack_time: String,
+    /// Bits of entropy of the generator, so assuming the attacker knows the policy
+    pub entropy: f64,
+}
+
+impl GeneratedPassword {
+    pub(super) fn new(password: String, estimation: &Entropy, entropy: f64) -> Self {
+        Self {
+            password,
+            score: estimation.score(),
+            crack_time: estimation
+                .crack_times()
+                .offline_slow_hashing_1e4_per_second()
+                .to_string(),
+            entropy: (entropy * 10.0).round() / 10.0,
+        }
+    }
+}
+
+/// A template with one slot per password character, e.g. `Cvccvc-99-Cvccvc`
+/// or `[A-Z]{4}-[0-9]{4}`:
+///
+/// - `c`/`C`: lower/upper case consonant, `v`/`V`: lower/upper case vowel
+/// - `a`/`A`: lower/upper case letter, `9`: digit, `s`: symbol
+/// - `[...]`: one of the listed characters, with ranges such as `a-f`
+/// - `{n}`: the previous slot repeated `n` times in all
+/// - `\x`: a literal `x`; other punctuation and spaces are literal as is
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pattern {
+    slots: Vec<Slot>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Slot {
+    Literal(char),
+    Class(Vec<char>),
+    Symbol,
+}
+
+impl Pattern {
+    /// Alternating consonants and vowels, e.g. `tovilumake`.
+    pub fn pronounceable(length: usize) -> Self {
+        let slots = (0..length)
+            .map(|i| {
+                let chars = if i % 2 == 0 { CONSONANT } else { VOWEL };
+                Slot::Class(chars.chars().collect())
+            })
+            .collect();
+        Self { slots }
+    }
+}
+
+impl FromStr for Pattern {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut slots = Vec::new();
+        let mut chars = s.chars().peekable();
+        while let Some(c) = chars.next() {
+            let slot = match c {
+                'c' => Slot::Class(CONSONANT.chars().collect()),
+                'C' => Slot::Class(CONSONANT.to_ascii_uppercase().chars().collect()),
+                'v' => Slot::Class(VOWEL.chars().collect()),
+                'V' => Slot::Class(VOWEL.to_ascii_uppercase().chars().collect()),
+                'a' => Slot::Class(LOWER.chars().collect()),
+                'A' => Slot::Class(UPPER.chars().collect()),
+                '9' => Slot::Class(NUMBER.chars().collect()),
+                's' => Slot::Symbol,
+                '[' => Slot::Class(parse_set(&mut chars)?),
+                '{' => {
+                    let mut count = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => count.push(c),
+                            None => return Err(anyhow!("unclosed `{{` in pattern")),
+                        }
+                    }
+                    let count = count
+                        .parse::<usize>()
+                        .map_err(|_| anyhow!("invalid repeat count `{{{}}}` in pattern", count))?;
+                    let slot = slots
+                        .pop()
+                        .ok_or_else(|| anyhow!("`{{` must follow a slot in pattern"))?;
+                    if slots.len() + count > MAX_PATTERN_LEN {
+                        return Err(anyhow!(
+                            "pattern expands to more than {} characters",
+                            MAX_PATTERN_LEN
+                        ));
+                    }
+                    slots.extend(std::iter::repeat_n(slot, count));
+                    continue;
+                }
+                '\\' => Slot::Literal(
+                    chars
+                        .next()
+                        .ok_or_else(|| anyhow!("pattern ends with a lone `\\`"))?,
+                ),
+                c if c.is_ascii_alphanumeric() => {
+                    return Err(anyhow!(
+                        "unknown placeholder `{}` in pattern, escape literals with `\\`",
+                        c
+                    ))
+                }
+                c => Slot::Literal(c),
+            };
+            slots.push(slot);
+        }
+        if slots.is_empty() {
+            return Err(anyhow!("empty pattern"));
+        }
+        Ok(Self { slots })
+    }
+}
+
+// the characters of a `[...]` set, the opening bracket already consumed
+fn parse_set(chars: &mut Peekable<Chars>) -> Result<Vec<char>> {
+    let mut set = Vec::new();
+    loop {
+        let c = match chars.next() {
+            Some(']') => break,
+            Some('\\') => chars.next(),
+            c => c,
+        }
+        .ok_or_else(|| anyhow!("unclosed `[` in pattern"))?;
+        let mut lookahead = chars.clone();
+        match (lookahead.next(), lookahead.next()) {
+            (Some('-'), Some(end)) if end != ']' => {
+                if end < c {
+                    return Err(anyhow!("invalid range `{}-{}` in pattern", c, end));
+                }
+                set.extend(c..=end);
+                chars.next();
+                chars.next();
+            }
+            _ => set.push(c),
+        }
+    }
+    if set.is_empty() {
+        return Err(anyhow!("empty `[]` in pattern"));
+    }
+    Ok(set)
+}
+
+/// Rules a generated password must satisfy. A character class is enabled when
+/// its minimum count is `Some`, which may be zero.
+#[derive(Debug, Clone)]
+pub struct PasswordPolicy {
+    /// Generate from this template instead, ignoring the length and classes
+    pub pattern: Option<Pattern>,
+    pub length: usize,
+    pub uppercase: Option<usize>,
+    pub lowercase: Option<usize>,
+    pub number: Option<usize>,
+    pub symbol: Option<usize>,
+    /// Characters making up the symbol class
+    pub symbols: String,
+    /// Leave out 0, O, 1, l and I
+    pub exclude_ambiguous: bool,
+    /// Characters never to use
+    pub exclude_chars: String,
+    /// No character directly followed by itself, e.g. `aa`
+    pub no_repeat: bool,
+    /// No runs of three ascending or descending letters or digits, e.g. `abc`, `321`
+    pub no_sequential: bool,
+    /// Minimum zxcvbn score, 0 to 4
+    pub min_score: u8,
+}
+
+impl Default for PasswordPolicy {
+    fn default() -> Self {
+        Self {
+            pattern: None,
+            length: 16,
+            uppercase: Some(1),
+            lowercase: Some(1),
+            number: Some(1),
+            symbol: Some(1),
+            symbols: SYMBOL.to_string(),
+            exclude_ambiguous: false,
+            exclude_chars: String::new(),
+            no_repeat: false,
+            no_sequential: false,
+            min_score: 0,
+        }
+    }
+}
+
+impl PasswordPolicy {
+    // (minimum count, characters) of every enabled class, exclusions applied
+    fn classes(&self) -> Result<Vec<(usize, Vec<char>)>> {
+        let classes = [
+            ("uppercase", self.uppercase, UPPER),
+            ("lowercase", self.lowercase, LOWER),
+            ("number", self.number, NUMBER),
+            ("symbol", self.symbol, self.symbols.as_str()),
+        ];
+        let mut ret = Vec::new();
+        for (name, min, chars) in classes {
+            let Some(min) = min else { continue };
+            ret.push((min, self.available(name, chars.chars())?));
+        }
+        let required = ret.iter().map(|(min, _)| min).sum::<usize>();
+        if required > self.length {
+            return Err(anyhow!(
+                "length {} is shorter than the {} characters the policy requires",
+                self.length,
+                required
+            ));
+        }
+        Ok(ret)
+    }
+
+    // the choices for every slot of the pattern, exclusions applied to all but literals
+    fn slots(&self, pattern: &Pattern) -> Result<Vec<Vec<char>>> {
+        pattern
+            .slots
+            .iter()
+            .map(|slot| match slot {
+                Slot::Literal(c) => Ok(vec![*c]),
+                Slot::Class(chars) => self.available("pattern", chars.iter().copied()),
+                Slot::Symbol => self.available("symbol", self.symbols.chars()),
+            })
+            .collect()
+    }
+
+    fn available(&self, name: &str, chars: impl Iterator<Item = char>) -> Result<Vec<char>> {
+        let mut chars = chars
+            .filter(|c| !self.exclude_chars.contains(*c))
+            .filter(|c| !(self.exclude_ambiguous && AMBIGUOUS.contains(*c)))
+            .collect::<Vec<_>>();
+        chars.sort_unstable();
+        chars.dedup();
+        if chars.is_empty() {
+            return Err(anyhow!("no {} characters left after exclusions", name));
+        }
+        Ok(chars)
+    }
+
+    fn allows(&self, password: &[char]) -> bool {
+        if self.no_repeat && password.windows(2).any(|w| w[0] == w[1]) {
+            return false;
+        }
+        if self.no_sequential && password.windows(3).any(is_sequence) {
+            return false;
+        }
+        true
+    }
+}
+
+fn is_sequence(chars: &[char]) -> bool {
+    let same_kind = chars.iter().all(char::is_ascii_digit)
+        || chars.iter().all(char::is_ascii_lowercase)
+        || chars.iter().all(char::is_ascii_uppercase);
+    let steps = chars
+        .windows(2)
+        .map(|w| w[1] as i32 - w[0] as i32)
+        .collect::<Vec<_>>();
+    same_kind && (steps.iter().all(|s| *s == 1) || steps.iter().all(|s| *s == -1))
+}
+
+/// Generate a password satisfying the policy. Candidates breaking a
+/// constraint are thrown away and regenerated rather than patched, so every
+/// valid password stays equally likely.
 pub fn process_genpass(
-    length: u8,
-    uppercase: bool,
-    lowercase: bool,
-    number: bool,
-    symbol: bool,
-) -> Result<String> {
-    let mut rng = rand::thread_rng();
-    let mut password = Vec::new();
-    let mut chars = Vec::new();
-
-    if uppercase {
-        chars.extend_from_slice(UPPER);
-        password.push(*UPPER.choose(&mut rng).expect("UPPER won't be empty"));
-    }
-    if lowercase {
-        chars.extend_from_slice(LOWER);
-        password.push(*LOWER.choose(&mut rng).expect("LOWER won't be empty"));
-    }
-    if number {
-        chars.extend_from_slice(NUMBER);
-        password.push(*NUMBER.choose(&mut rng).expect("NUMBER won't be empty"));
-    }
-    if symbol {
-        chars.extend_from_slice(SYMBOL);
-        password.push(*SYMBOL.choose(&mut rng).expect("SYMBOL won't be empty"));
-    }
-
-    for _ in 0..(length - password.len() as u8) {
-        password.push(chars[rng.gen_range(0..chars.len())]);
-    }
-
-    password.shuffle(&mut rng);
-    let password = String::from_utf8(password).unwrap();
-    let estimation = zxcvbn(&password, &[]).unwrap();
-    println!("Password: {}, Score: {}", password, estimation.score());
-    Ok(password)
+    policy: &PasswordPolicy,
+    rng: &mut (impl Rng + CryptoRng),
+) -> Result<GeneratedPassword> {
+    if let Some(pattern) = &policy.pattern {
+        let slots = policy.slots(pattern)?;
+        let entropy = slots.iter().map(|s| (s.len() as f64).log2()).sum();
+        return generate(policy, entropy, || {
+            slots
+                .iter()
+                .map(|s| *s.choose(rng).expect("slots are never empty"))
+                .collect()
+        });
+    }
+
+    let classes = policy.classes()?;
+    if classes.is_empty() {
+        return Err(anyhow!("no character class enabled"));
+    }
+    let all = classes
+        .iter()
+        .flat_map(|(_, chars)| chars)
+        .copied()
+        .collect::<Vec<_>>();
+    // every character is drawn from the whole set, give or take the minimums
+    let entropy = policy.length as f64 * (all.len() as f64).log2();
+    generate(policy, entropy, || {
+        let mut password = Vec::with_capacity(policy.length);
+        for (min, chars) in &classes {
+            for _ in 0..*min {
+                password.push(*chars.choose(rng).expect("classes are never empty"));
+            }
+        }
+        while password.len() < policy.length {
+            password.push(all[rng.gen_range(0..all.len())]);
+        }
+        password.shuffle(rng);
+        password
+    })
+}
+
+fn generate(
+    policy: &PasswordPolicy,
+    entropy: f64,
+    mut candidate: impl FnMut() -> Vec<char>,
+) -> Result<GeneratedPassword> {
+    for _ in 0..MAX_ATTEMPTS {
+        let password = candidate();
+        if !policy.allows(&password) {
+            continue;
+        }
+
+        let password = password.into_iter().collect::<String>();
+        let estimation = zxcvbn(&password, &[])?;
+        if estimation.score() < policy.min_score {
+            continue;
+        }
+        return Ok(GeneratedPassword::new(password, &estimation, entropy));
+    }
+    Err(anyhow!(
+        "no password satisfying the policy found in {} attempts",
+        MAX_ATTEMPTS
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{thread_rng, SeedableRng};
+    use rand_chacha::ChaCha20Rng;
+
+    #[test]
+    fn test_genpass_minimum_counts() -> Result<()> {
+        let policy = PasswordPolicy {
+            length: 12,
+            uppercase: Some(3),
+            lowercase: None,
+            number: Some(4),
+            symbol: Some(2),
+            symbols: "#!".to_string(),
+            ..Default::default()
+        };
+        for _ in 0..20 {
+            let password = process_genpass(&policy, &mut thread_rng())?.password;
+            assert_eq!(password.chars().count(), 12);
+            assert!(password.chars().filter(char::is_ascii_uppercase).count() >= 3);
+            assert!(password.chars().filter(char::is_ascii_digit).count() >= 4);
+            assert!(password.chars().filter(|c| "#!".contains(*c)).count() >= 2);
+            assert!(!password.chars().any(|c| c.is_ascii_lowercase()));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_genpass_exclusions_and_constraints() -> Result<()> {
+        let policy = PasswordPolicy {
+            length: 32,
+            exclude_ambiguous: true,
+            exclude_chars: "aeiou".to_string(),
+            no_repeat: true,
+            no_sequential: true,
+            min_score: 3,
+            ..Default::default()
+        };
+        for _ in 0..20 {
+            let password = process_genpass(&policy, &mut thread_rng())?
+                .password
+                .chars()
+                .collect::<Vec<_>>();
+            assert!(!password.iter().any(|c| "0O1lIaeiou".contains(*c)));
+            assert!(policy.allows(&password));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_genpass_rejects_impossible_policies() {
+        let too_short = PasswordPolicy {
+            length: 3,
+            ..Default::default()
+        };
+        assert!(process_genpass(&too_short, &mut thread_rng()).is_err());
+
+        let no_digits = PasswordPolicy {
+            exclude_chars: NUMBER.to_string(),
+            ..Default::default()
+        };
+        assert!(process_genpass(&no_digits, &mut thread_rng()).is_err());
+
+        let no_classes = PasswordPolicy {
+            uppercase: None,
+            lowercase: None,
+            number: None,
+            symbol: None,
+            ..Default::default()
+        };
+        assert!(process_genpass(&no_classes, &mut thread_rng()).is_err());
+    }
+
+    #[test]
+    fn test_genpass_long_secret() -> Result<()> {
+        let policy = PasswordPolicy {
+            length: 300,
+            symbol: None,
+            ..Default::default()
+        };
+        let password = process_genpass(&policy, &mut thread_rng())?;
+        assert_eq!(password.password.len(), 300);
+        assert!(password.entropy > 1700.0);
+        assert_eq!(password.score, 4);
+        Ok(())
+    }
+
+    #[test]
+    fn test_genpass_pattern() -> Result<()> {
+        let policy = PasswordPolicy {
+            pattern: Some("Cvccvc-99-[A-F0-9]{4}".parse()?),
+            exclude_ambiguous: true,
+            ..Default::default()
+        };
+        for _ in 0..20 {
+            let password = process_genpass(&policy, &mut thread_rng())?.password;
+            let chars = password.chars().collect::<Vec<_>>();
+            assert_eq!(chars.len(), 14);
+            assert!(
+                !CONSONANT.contains(chars[0]) && CONSONANT.contains(chars[0].to_ascii_lowercase())
+            );
+            assert!(VOWEL.contains(chars[1]));
+            assert_eq!((chars[6], chars[9]), ('-', '-'));
+            assert!(chars[7..9].iter().all(|c| "23456789".contains(*c)));
+            assert!(chars[10..].iter().all(|c| "ABCDEF23456789".contains(*c)));
+        }
+
+        let pin = PasswordPolicy {
+            pattern: Some("9{6}".parse()?),
+            ..Default::default()
+        };
+        let pin = process_genpass(&pin, &mut thread_rng())?;
+        assert!(pin.password.len() == 6 && pin.password.chars().all(|c| c.is_ascii_digit()));
+        assert!((pin.entropy - 19.9).abs() < 0.01);
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_pattern() {
+        let parse = |s: &str| s.parse::<Pattern>();
+        let digits = Slot::Class(NUMBER.chars().collect());
+        assert_eq!(
+            parse("\\a-9{2}").unwrap().slots,
+            [
+                Slot::Literal('a'),
+                Slot::Literal('-'),
+                digits.clone(),
+                digits
+            ]
+        );
+        assert_eq!(
+            parse("[x\\]a-c-]").unwrap().slots,
+            [Slot::Class(vec!['x', ']', 'a', 'b', 'c', '-'])]
+        );
+        assert_eq!(parse("9s{0}").unwrap().slots.len(), 1);
+        for invalid in [
+            "", "s{0}", "b", "[a-", "[]", "[z-a]", "{2}", "9{x}", "9{3", "9\\",
+        ] {
+            assert!(parse(invalid).is_err(), "{}", invalid);
+        }
+        assert_eq!(
+            parse("9{3").unwrap_err().to_string(),
+            "unclosed `{` in pattern"
+        );
+        assert_eq!(parse("9{65535}").unwrap().slots.len(), 65535);
+        for too_long in ["9{1000000000}", "a9{65535}"] {
+            assert_eq!(
+                parse(too_long).unwrap_err().to_string(),
+                "pattern expands to more than 65535 characters"
+            );
+        }
+    }
+
+    #[test]
+    fn test_pronounceable() -> Result<()> {
+        let policy = PasswordPolicy {
+            pattern: Some(Pattern::pronounceable(9)),
+            ..Default::default()
+        };
+        let password = process_genpass(&policy, &mut thread_rng())?.password;
+        assert_eq!(password.len(), 9);
+        for (i, c) in password.chars().enumerate() {
+            let chars = if i % 2 == 0 { CONSONANT } else { VOWEL };
+            assert!(chars.contains(c));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_genpass_seeded() -> Result<()> {
+        let policy = PasswordPolicy::default();
+        let mut rng = ChaCha20Rng::seed_from_u64(42);
+        let first = process_genpass(&policy, &mut rng)?.password;
+        let second = process_genpass(&policy, &mut rng)?.password;
+        assert_eq!(first, "+!+g!=4|}]s\\qQNq");
+        assert_eq!(second, "9TxuFaquZv._E2[z");
+
+        let pattern = PasswordPolicy {
+            pattern: Some("[A-Z]{4}-9{4}".parse()?),
+            ..Default::default()
+        };
+        let key = process_genpass(&pattern, &mut ChaCha20Rng::seed_from_u64(42))?;
+        assert_eq!(key.password, "NQKA-6389");
+        Ok(())
+    }
+
+    #[test]
+    fn test_policy_constraints() {
+        let policy = PasswordPolicy {
+            no_repeat: true,
+            no_sequential: true,
+            ..Default::default()
+        };
+        let allows = |s: &str| policy.allows(&s.chars().collect::<Vec<_>>());
+        assert!(allows("a1b2c3"));
+        assert!(!allows("xaab"));
+        assert!(!allows("x123"));
+        assert!(!allows("CBA9"));
+        assert!(allows("ab-c"));
+        assert!(allows("9:;"));
+    }
 }
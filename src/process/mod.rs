@@ -1,7 +1,32 @@
 mod b64;
+mod convert;
 mod csv_convert;
+mod csv_dialect;
+mod csv_diff;
+mod csv_export;
+mod csv_join;
+mod csv_query;
+mod csv_schema;
+mod csv_show;
+mod csv_stats;
+mod csv_validate;
 mod gen_pass;
 mod http_serve;
+mod passphrase;
+mod password_check;
+mod secret;
 mod text;
 
-pub use self::{b64::*, csv_convert::*, gen_pass::*, http_serve::*, text::*};
+pub use self::{
+    b64::*, convert::*, csv_convert::*, csv_diff::*, csv_export::*, csv_join::*, csv_show::*,
+    csv_stats::*, csv_validate::*, gen_pass::*, http_serve::*, passphrase::*, password_check::*,
+    secret::*, text::*,
+};
+
+// write a test input into `dir`, returning its path
+#[cfg(test)]
+fn fixture(dir: &tempfile::TempDir, name: &str, content: &str) -> anyhow::Result<String> {
+    let path = dir.path().join(name);
+    std::fs::write(&path, content)?;
+    Ok(path.to_string_lossy().into_owned())
+}
@@ -0,0 +1,251 @@
+use std::{fs, path::Path, str::FromStr};
+
+use anyhow::{anyhow, Result};
+use rand::{seq::SliceRandom, CryptoRng, Rng};
+use zxcvbn::zxcvbn;
+
+use super::gen_pass::{GeneratedPassword, MAX_ATTEMPTS, SYMBOL};
+
+// 7776 words, one per roll of five dice: `11111<TAB>abacus`
+const EFF_LARGE_WORDLIST: &str = include_str!("../../assets/eff_large_wordlist.txt");
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WordCase {
+    Lower,
+    Title,
+    Upper,
+}
+
+impl FromStr for WordCase {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "lower" => Ok(WordCase::Lower),
+            "title" => Ok(WordCase::Title),
+            "upper" => Ok(WordCase::Upper),
+            _ => Err(anyhow!("Invalid word case")),
+        }
+    }
+}
+
+/// The words a passphrase is drawn from, deduplicated, so that they are read
+/// and parsed once however many passphrases are generated.
+#[derive(Debug, Clone)]
+pub struct Wordlist {
+    words: Vec<String>,
+}
+
+impl Wordlist {
+    /// Read a word list file, one word per line optionally preceded by its
+    /// dice roll.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        fs::read_to_string(path)?.parse()
+    }
+}
+
+/// The EFF large wordlist.
+impl Default for Wordlist {
+    fn default() -> Self {
+        EFF_LARGE_WORDLIST
+            .parse()
+            .expect("the EFF wordlist is valid")
+    }
+}
+
+impl FromStr for Wordlist {
+    type Err = anyhow::Error;
+
+    // the last field of every non-empty line
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut words = s
+            .lines()
+            .filter_map(|line| line.split_whitespace().last())
+            .collect::<Vec<_>>();
+        words.sort_unstable();
+        words.dedup();
+        if words.len() < 2 {
+            return Err(anyhow!("the word list needs at least two distinct words"));
+        }
+        Ok(Self {
+            words: words.into_iter().map(String::from).collect(),
+        })
+    }
+}
+
+/// How to build a diceware passphrase.
+#[derive(Debug, Clone)]
+pub struct PassphrasePolicy {
+    pub words: usize,
+    pub separator: String,
+    pub case: WordCase,
+    /// Append a random digit
+    pub digit: bool,
+    /// Append a random character from `symbols`
+    pub symbol: bool,
+    pub symbols: String,
+    pub wordlist: Wordlist,
+    /// Minimum zxcvbn score, 0 to 4
+    pub min_score: u8,
+}
+
+impl Default for PassphrasePolicy {
+    fn default() -> Self {
+        Self {
+            words: 6,
+            separator: "-".to_string(),
+            case: WordCase::Lower,
+            digit: false,
+            symbol: false,
+            symbols: SYMBOL.to_string(),
+            wordlist: Wordlist::default(),
+            min_score: 0,
+        }
+    }
+}
+
+impl PassphrasePolicy {
+    /// Bits of entropy of a passphrase built by this policy.
+    pub fn entropy(&self) -> f64 {
+        let list_len = self.wordlist.words.len();
+        let mut bits = self.words as f64 * (list_len as f64).log2();
+        if self.digit {
+            bits += 10f64.log2();
+        }
+        if self.symbol {
+            bits += (self.symbols.chars().count() as f64).log2();
+        }
+        bits
+    }
+}
+
+pub fn process_passphrase(
+    policy: &PassphrasePolicy,
+    rng: &mut (impl Rng + CryptoRng),
+) -> Result<GeneratedPassword> {
+    let words = &policy.wordlist.words;
+    if policy.words == 0 {
+        return Err(anyhow!("a passphrase needs at least one word"));
+    }
+    let symbols = policy.symbols.chars().collect::<Vec<_>>();
+    if policy.symbol && symbols.is_empty() {
+        return Err(anyhow!("no symbols to append"));
+    }
+
+    let entropy = policy.entropy();
+    for _ in 0..MAX_ATTEMPTS {
+        let mut passphrase = (0..policy.words)
+            .map(|_| {
+                let word = words.choose(rng).expect("word lists are never empty");
+                match policy.case {
+                    WordCase::Lower => word.to_lowercase(),
+                    WordCase::Upper => word.to_uppercase(),
+                    WordCase::Title => title_case(word),
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(&policy.separator);
+        if policy.digit {
+            passphrase.push(char::from(b'0' + rng.gen_range(0..10)));
+        }
+        if policy.symbol {
+            passphrase.push(*symbols.choose(rng).expect("checked above"));
+        }
+
+        let estimation = zxcvbn(&passphrase, &[])?;
+        if estimation.score() >= policy.min_score {
+            return Ok(GeneratedPassword::new(passphrase, &estimation, entropy));
+        }
+    }
+    Err(anyhow!(
+        "no passphrase reaching score {} found in {} attempts",
+        policy.min_score,
+        MAX_ATTEMPTS
+    ))
+}
+
+fn title_case(word: &str) -> String {
+    let mut chars = word.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{thread_rng, SeedableRng};
+    use rand_chacha::ChaCha20Rng;
+
+    #[test]
+    fn test_eff_wordlist() -> Result<()> {
+        let policy = PassphrasePolicy::default();
+        assert_eq!(policy.wordlist.words.len(), 7776);
+        assert!((policy.entropy() - 77.55).abs() < 0.01);
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_passphrase() -> Result<()> {
+        let policy = PassphrasePolicy {
+            words: 4,
+            separator: " ".to_string(),
+            case: WordCase::Title,
+            digit: true,
+            symbol: true,
+            symbols: "!".to_string(),
+            ..Default::default()
+        };
+        let passphrase = process_passphrase(&policy, &mut thread_rng())?.password;
+        assert!(passphrase.ends_with('!'));
+        let digit = passphrase.chars().nth_back(1).unwrap();
+        assert!(digit.is_ascii_digit());
+        let words = passphrase[..passphrase.len() - 2]
+            .split(' ')
+            .collect::<Vec<_>>();
+        assert_eq!(words.len(), 4);
+        assert!(words.iter().all(|w| w.starts_with(char::is_uppercase)));
+        Ok(())
+    }
+
+    #[test]
+    fn test_passphrase_seeded() -> Result<()> {
+        let policy = PassphrasePolicy {
+            words: 4,
+            digit: true,
+            ..Default::default()
+        };
+        let passphrase = process_passphrase(&policy, &mut ChaCha20Rng::seed_from_u64(7))?;
+        assert_eq!(passphrase.password, "compacted-anagram-subsiding-numbing7");
+
+        let strong = PassphrasePolicy {
+            words: 2,
+            min_score: 4,
+            ..Default::default()
+        };
+        for _ in 0..5 {
+            assert_eq!(process_passphrase(&strong, &mut thread_rng())?.score, 4);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_user_wordlist() -> Result<()> {
+        let file = tempfile::NamedTempFile::new()?;
+        let path = file.path();
+        fs::write(path, "11\tjuve\n12\ttorino\n\n")?;
+        let policy = PassphrasePolicy {
+            words: 3,
+            wordlist: Wordlist::load(path)?,
+            ..Default::default()
+        };
+        let passphrase = process_passphrase(&policy, &mut thread_rng())?.password;
+        assert!(passphrase.split('-').all(|w| w == "juve" || w == "torino"));
+        assert_eq!(policy.entropy(), 3.0);
+
+        fs::write(path, "only\n")?;
+        assert!(Wordlist::load(path).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,122 @@
+use anyhow::Result;
+use serde::Serialize;
+use zxcvbn::zxcvbn;
+
+#[derive(Debug, Serialize)]
+pub struct PasswordCheck {
+    /// zxcvbn score, 0 to 4
+    pub score: u8,
+    pub guesses: u64,
+    pub guesses_log10: f64,
+    pub crack_times: Vec<CrackTime>,
+    pub warning: Option<String>,
+    pub suggestions: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CrackTime {
+    pub scenario: &'static str,
+    pub time: String,
+}
+
+/// Estimate the strength of a password. `user_inputs` such as the username or
+/// email are treated as dictionary words, so passwords built on them score lower.
+pub fn process_password_check(password: &str, user_inputs: &[&str]) -> Result<PasswordCheck> {
+    let estimation = zxcvbn(password, user_inputs)?;
+    let times = estimation.crack_times();
+    let crack_times = [
+        (
+            "online, throttled (100/hour)",
+            times.online_throttling_100_per_hour(),
+        ),
+        (
+            "online, unthrottled (10/s)",
+            times.online_no_throttling_10_per_second(),
+        ),
+        (
+            "offline, slow hash (1e4/s)",
+            times.offline_slow_hashing_1e4_per_second(),
+        ),
+        (
+            "offline, fast hash (1e10/s)",
+            times.offline_fast_hashing_1e10_per_second(),
+        ),
+    ]
+    .into_iter()
+    .map(|(scenario, time)| CrackTime {
+        scenario,
+        time: time.to_string(),
+    })
+    .collect();
+    let feedback = estimation.feedback().as_ref();
+    Ok(PasswordCheck {
+        score: estimation.score(),
+        guesses: estimation.guesses(),
+        guesses_log10: estimation.guesses_log10(),
+        crack_times,
+        warning: feedback.and_then(|f| f.warning()).map(|w| w.to_string()),
+        suggestions: feedback
+            .map(|f| f.suggestions().iter().map(|s| s.to_string()).collect())
+            .unwrap_or_default(),
+    })
+}
+
+/// Render a check as an indented report headed by `label`, e.g. the line number.
+pub fn format_password_check(label: &str, check: &PasswordCheck) -> String {
+    // large counts saturate, so show them as a power of ten
+    let guesses = if check.guesses_log10 < 6.0 {
+        check.guesses.to_string()
+    } else {
+        format!("10^{:.1}", check.guesses_log10)
+    };
+    let mut ret = format!("{}: score {}/4, {} guesses\n", label, check.score, guesses);
+    let width = check
+        .crack_times
+        .iter()
+        .map(|t| t.scenario.len())
+        .max()
+        .unwrap_or_default();
+    for t in &check.crack_times {
+        ret.push_str(&format!(
+            "  {:<width$}  {}\n",
+            format!("{}:", t.scenario),
+            t.time,
+            width = width + 1
+        ));
+    }
+    if let Some(warning) = &check.warning {
+        ret.push_str(&format!("  warning: {}\n", warning));
+    }
+    for suggestion in &check.suggestions {
+        ret.push_str(&format!("  suggestion: {}\n", suggestion));
+    }
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_process_password_check() -> Result<()> {
+        let weak = process_password_check("password", &[])?;
+        assert_eq!(weak.score, 0);
+        assert!(weak.warning.is_some());
+        assert_eq!(weak.crack_times.len(), 4);
+
+        let strong = process_password_check("correct-horse-battery-staple-juve", &[])?;
+        assert_eq!(strong.score, 4);
+
+        // user inputs count as dictionary words
+        let plain = process_password_check("dybala1993", &[])?;
+        let hinted = process_password_check("dybala1993", &["dybala"])?;
+        assert!(hinted.guesses < plain.guesses);
+
+        let report = format_password_check("line 1", &weak);
+        assert!(report.starts_with("line 1: score 0/4, 3 guesses\n"));
+        assert!(format_password_check("", &strong).contains("/4, 10^"));
+        assert!(report.contains("  offline, fast hash (1e10/s):  "));
+        assert!(report.contains("  warning: "));
+        Ok(())
+    }
+}
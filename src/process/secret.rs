@@ -0,0 +1,178 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{anyhow, Result};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use data_encoding::{BASE32_NOPAD, HEXLOWER};
+use rand::{CryptoRng, Rng};
+use uuid::Builder;
+
+use crate::cli::{TokenEncoding, UuidVersion};
+
+/// `len` random bytes, e.g. a signing key.
+pub fn random_bytes(len: usize, rng: &mut (impl Rng + CryptoRng)) -> Vec<u8> {
+    let mut bytes = vec![0; len];
+    rng.fill_bytes(&mut bytes);
+    bytes
+}
+
+/// How to build an API token: the prefix, the encoded random bytes and
+/// optionally a checksum, e.g. `sk_live_<bytes><checksum>`.
+#[derive(Debug, Clone)]
+pub struct TokenPolicy {
+    pub bytes: usize,
+    pub encoding: TokenEncoding,
+    pub prefix: String,
+    pub checksum: bool,
+}
+
+impl Default for TokenPolicy {
+    fn default() -> Self {
+        Self {
+            bytes: 32,
+            encoding: TokenEncoding::Base64Url,
+            prefix: String::new(),
+            checksum: true,
+        }
+    }
+}
+
+/// Generate an API token. The checksum is the CRC32 of everything before it,
+/// encoded like the rest of the token at a fixed width, so a secret scanner
+/// can tell a leaked token from a random string that merely shares the prefix.
+pub fn process_token(policy: &TokenPolicy, rng: &mut (impl Rng + CryptoRng)) -> Result<String> {
+    if policy.bytes == 0 {
+        return Err(anyhow!("a token needs at least one random byte"));
+    }
+    let mut token = policy.prefix.clone();
+    token.push_str(&encode(&random_bytes(policy.bytes, rng), policy.encoding));
+    if policy.checksum {
+        let checksum = checksum(&token, policy.encoding);
+        token.push_str(&checksum);
+    }
+    Ok(token)
+}
+
+/// Whether the token ends with the checksum of the rest of it.
+pub fn verify_token(token: &str, encoding: TokenEncoding) -> bool {
+    let width = checksum_width(encoding);
+    if token.len() <= width || !token.is_char_boundary(token.len() - width) {
+        return false;
+    }
+    let (body, tail) = token.split_at(token.len() - width);
+    checksum(body, encoding) == tail
+}
+
+/// A random (v4) or time-ordered (v7) UUID in its hyphenated form.
+pub fn process_uuid(version: UuidVersion, rng: &mut (impl Rng + CryptoRng)) -> Result<String> {
+    let bytes = rng.gen::<[u8; 16]>();
+    let uuid = match version {
+        UuidVersion::V4 => Builder::from_random_bytes(bytes).into_uuid(),
+        UuidVersion::V7 => {
+            let millis = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis();
+            let random = bytes[..10].try_into()?;
+            Builder::from_unix_timestamp_millis(millis as u64, &random).into_uuid()
+        }
+    };
+    Ok(uuid.to_string())
+}
+
+fn encode(bytes: &[u8], encoding: TokenEncoding) -> String {
+    match encoding {
+        TokenEncoding::Hex => HEXLOWER.encode(bytes),
+        TokenEncoding::Base64Url => URL_SAFE_NO_PAD.encode(bytes),
+        TokenEncoding::Base32 => BASE32_NOPAD.encode(bytes),
+        TokenEncoding::Base58 => bs58::encode(bytes).into_string(),
+    }
+}
+
+fn checksum(token: &str, encoding: TokenEncoding) -> String {
+    let crc = crc32fast::hash(token.as_bytes()).to_be_bytes();
+    // base58 output varies in length, `1` being its zero digit
+    format!(
+        "{:1>width$}",
+        encode(&crc, encoding),
+        width = checksum_width(encoding)
+    )
+}
+
+// characters a four byte CRC32 takes in each encoding
+fn checksum_width(encoding: TokenEncoding) -> usize {
+    match encoding {
+        TokenEncoding::Hex => 8,
+        TokenEncoding::Base64Url | TokenEncoding::Base58 => 6,
+        TokenEncoding::Base32 => 7,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{thread_rng, SeedableRng};
+    use rand_chacha::ChaCha20Rng;
+
+    #[test]
+    fn test_process_token() -> Result<()> {
+        let encodings = [
+            (TokenEncoding::Hex, 64 + 8),
+            (TokenEncoding::Base64Url, 43 + 6),
+            (TokenEncoding::Base32, 52 + 7),
+        ];
+        for (encoding, len) in encodings {
+            let policy = TokenPolicy {
+                encoding,
+                prefix: "sk_live_".to_string(),
+                ..Default::default()
+            };
+            let token = process_token(&policy, &mut thread_rng())?;
+            assert_eq!(token.len(), "sk_live_".len() + len);
+            assert!(token.starts_with("sk_live_"));
+            assert!(verify_token(&token, encoding));
+        }
+
+        let policy = TokenPolicy {
+            bytes: 16,
+            encoding: TokenEncoding::Base58,
+            prefix: "rcli_".to_string(),
+            checksum: true,
+        };
+        let token = process_token(&policy, &mut ChaCha20Rng::seed_from_u64(42))?;
+        assert_eq!(token, "rcli_FrV7UAxN5Vzuk4dG9qStRe79mvVz");
+        assert!(verify_token(&token, TokenEncoding::Base58));
+        Ok(())
+    }
+
+    #[test]
+    fn test_verify_token() -> Result<()> {
+        let policy = TokenPolicy {
+            encoding: TokenEncoding::Hex,
+            ..Default::default()
+        };
+        let token = process_token(&policy, &mut thread_rng())?;
+        let mut tampered = token.clone();
+        let flipped = if tampered.starts_with('a') { "b" } else { "a" };
+        tampered.replace_range(..1, flipped);
+        assert!(!verify_token(&tampered, TokenEncoding::Hex));
+        assert!(!verify_token("1234", TokenEncoding::Hex));
+
+        let unchecked = TokenPolicy {
+            checksum: false,
+            ..policy
+        };
+        assert_eq!(process_token(&unchecked, &mut thread_rng())?.len(), 64);
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_uuid() -> Result<()> {
+        let v4 = process_uuid(UuidVersion::V4, &mut ChaCha20Rng::seed_from_u64(42))?;
+        assert_eq!(v4, "781199f9-d55d-48e8-95a7-aa84c5e4b40d");
+        assert_eq!(uuid::Uuid::parse_str(&v4)?.get_version_num(), 4);
+
+        let first = process_uuid(UuidVersion::V7, &mut thread_rng())?;
+        std::thread::sleep(std::time::Duration::from_millis(2));
+        let second = process_uuid(UuidVersion::V7, &mut thread_rng())?;
+        assert_eq!(uuid::Uuid::parse_str(&first)?.get_version_num(), 7);
+        assert!(first < second);
+        Ok(())
+    }
+}
@@ -1,10 +1,10 @@
 use std::{fs, io::Read, path::Path};
 
-use crate::{cli::TextSignFormat, get_reader, process_genpass};
+use crate::{cli::TextSignFormat, get_reader, random_bytes};
 use anyhow::{Ok, Result};
 use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
 use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
-use rand::rngs::OsRng;
+use rand::{CryptoRng, Rng};
 
 trait TextSign {
     // sign the data from the reader and return the signature
@@ -127,21 +127,18 @@ impl TextVerify for Ed25519Verifier {
     }
 }
 pub trait KeyGenerator {
-    fn generate() -> Result<Vec<Vec<u8>>>;
+    fn generate(rng: &mut (impl Rng + CryptoRng)) -> Result<Vec<Vec<u8>>>;
 }
 
 impl KeyGenerator for Blake3 {
-    fn generate() -> Result<Vec<Vec<u8>>> {
-        let key = process_genpass(32, true, true, true, true)?;
-        let key = key.as_bytes().to_vec();
-        Ok(vec![key])
+    fn generate(rng: &mut (impl Rng + CryptoRng)) -> Result<Vec<Vec<u8>>> {
+        Ok(vec![random_bytes(32, rng)])
     }
 }
 
 impl KeyGenerator for Ed25519Signer {
-    fn generate() -> Result<Vec<Vec<u8>>> {
-        let mut csprng = OsRng;
-        let signing_key = SigningKey::generate(&mut csprng);
+    fn generate(rng: &mut (impl Rng + CryptoRng)) -> Result<Vec<Vec<u8>>> {
+        let signing_key = SigningKey::generate(rng);
         let pk = signing_key.verifying_key().to_bytes().to_vec();
         let sk = signing_key.as_bytes().to_vec();
         Ok(vec![sk, pk])
@@ -179,10 +176,13 @@ pub fn process_verify(input: &str, key: &str, format: TextSignFormat, sig: &[u8]
     }
 }
 
-pub fn process_generate(format: &TextSignFormat) -> Result<Vec<Vec<u8>>> {
+pub fn process_generate(
+    format: &TextSignFormat,
+    rng: &mut (impl Rng + CryptoRng),
+) -> Result<Vec<Vec<u8>>> {
     match format {
-        TextSignFormat::Blake3 => Blake3::generate(),
-        TextSignFormat::Ed25519 => Ed25519Signer::generate(),
+        TextSignFormat::Blake3 => Blake3::generate(rng),
+        TextSignFormat::Ed25519 => Ed25519Signer::generate(rng),
     }
 }
 
@@ -190,6 +190,8 @@ pub fn process_generate(format: &TextSignFormat) -> Result<Vec<Vec<u8>>> {
 mod tests {
 
     use super::*;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha20Rng;
 
     #[test]
     fn test_blake3_sign_verify() -> Result<()> {
@@ -210,4 +212,22 @@ mod tests {
         assert!(pk.verify(&data[..], &sig)?);
         Ok(())
     }
+
+    #[test]
+    fn test_generate_seeded() -> Result<()> {
+        let mut rng = ChaCha20Rng::seed_from_u64(42);
+        let keys = process_generate(&TextSignFormat::Ed25519, &mut rng)?;
+        let again = process_generate(
+            &TextSignFormat::Ed25519,
+            &mut ChaCha20Rng::seed_from_u64(42),
+        )?;
+        assert_eq!(keys, again);
+
+        let signer = Ed25519Signer::try_new(&keys[0])?;
+        let verifier = Ed25519Verifier::try_new(&keys[1])?;
+        let sig = signer.sign(&mut &b"hello world"[..])?;
+        assert!(verifier.verify(&b"hello world"[..], &sig)?);
+        assert_eq!(URL_SAFE_NO_PAD.encode(&sig), "B7rU8dX-GoB_YOFa76CAx54qk-irZ45Rib1Nu6cyv-cHw3ipVkLiEo4n8CkC2wvQMCG-6uNuV8oW9C5QsdYgCw");
+        Ok(())
+    }
 }
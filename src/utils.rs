@@ -1,6 +1,14 @@
-use std::{fs, io::Read};
+use std::{
+    fs,
+    io::{self, BufWriter, Read, Stdout, Write},
+    path::{Path, PathBuf},
+};
 
-use anyhow::Error;
+use anyhow::{anyhow, Error};
+use rand::{rngs::OsRng, CryptoRng, RngCore, SeedableRng};
+use rand_chacha::ChaCha20Rng;
+use tempfile::NamedTempFile;
+use tracing::warn;
 
 pub fn get_reader(input: &str) -> Result<Box<dyn Read>, Error> {
     let reader: Box<dyn Read> = if input == "-" {
@@ -11,3 +19,186 @@ pub fn get_reader(input: &str) -> Result<Box<dyn Read>, Error> {
 
     Ok(reader)
 }
+
+/// Open `output` for writing, `-` being stdout. Files are written to a
+/// temporary file next to the target and only moved into place by
+/// [`Output::commit`], so a failed run never leaves a truncated file behind.
+pub fn get_writer(output: &str, overwrite: bool) -> Result<Output, Error> {
+    if output == "-" {
+        return Ok(Output::Stdout(BufWriter::new(io::stdout())));
+    }
+    let path = PathBuf::from(output);
+    if !overwrite && path.exists() {
+        return Err(anyhow!("{} already exists", output));
+    }
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let mut builder = tempfile::Builder::new();
+    // temporary files are private; give the output the mode a plain create
+    // would, i.e. 0666 less the umask
+    #[cfg(unix)]
+    builder.permissions(std::os::unix::fs::PermissionsExt::from_mode(0o666));
+    let file = builder.tempfile_in(dir)?;
+    // and keep the mode of a file being replaced
+    if let Ok(metadata) = fs::metadata(&path) {
+        file.as_file().set_permissions(metadata.permissions())?;
+    }
+    Ok(Output::File {
+        file: BufWriter::new(file),
+        path,
+        overwrite,
+    })
+}
+
+pub enum Output {
+    Stdout(BufWriter<Stdout>),
+    File {
+        file: BufWriter<NamedTempFile>,
+        path: PathBuf,
+        overwrite: bool,
+    },
+}
+
+impl Output {
+    /// Flush and move the file into place. Dropping an uncommitted output
+    /// removes the temporary file.
+    pub fn commit(self) -> Result<(), Error> {
+        match self {
+            Output::Stdout(mut stdout) => stdout.flush()?,
+            Output::File {
+                file,
+                path,
+                overwrite,
+            } => {
+                let file = file.into_inner().map_err(|e| e.into_error())?;
+                if overwrite {
+                    file.persist(&path)?;
+                } else {
+                    file.persist_noclobber(&path)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Output::Stdout(stdout) => stdout.write(buf),
+            Output::File { file, .. } => file.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Output::Stdout(stdout) => stdout.flush(),
+            Output::File { file, .. } => file.flush(),
+        }
+    }
+}
+
+/// The OS CSPRNG, or with a seed a ChaCha20 stream whose output is the same on
+/// every run and every release. A seeded generator is only as secret as its
+/// seed, so it is meant for tests and fixtures and never for real secrets.
+pub fn get_rng(seed: Option<u64>) -> SecretRng {
+    match seed {
+        Some(seed) => {
+            warn!("--seed makes the output predictable, never use it for real secrets");
+            SecretRng::Seeded(Box::new(ChaCha20Rng::seed_from_u64(seed)))
+        }
+        None => SecretRng::Os(OsRng),
+    }
+}
+
+pub enum SecretRng {
+    Os(OsRng),
+    Seeded(Box<ChaCha20Rng>),
+}
+
+impl RngCore for SecretRng {
+    fn next_u32(&mut self) -> u32 {
+        match self {
+            SecretRng::Os(rng) => rng.next_u32(),
+            SecretRng::Seeded(rng) => rng.next_u32(),
+        }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        match self {
+            SecretRng::Os(rng) => rng.next_u64(),
+            SecretRng::Seeded(rng) => rng.next_u64(),
+        }
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        match self {
+            SecretRng::Os(rng) => rng.fill_bytes(dest),
+            SecretRng::Seeded(rng) => rng.fill_bytes(dest),
+        }
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        match self {
+            SecretRng::Os(rng) => rng.try_fill_bytes(dest),
+            SecretRng::Seeded(rng) => rng.try_fill_bytes(dest),
+        }
+    }
+}
+
+impl CryptoRng for SecretRng {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_writer_is_atomic() -> Result<(), Error> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("out.txt");
+        let output = path.to_str().unwrap();
+
+        let mut writer = get_writer(output, true)?;
+        writer.write_all(b"partial")?;
+        drop(writer);
+        assert!(!path.exists());
+        assert_eq!(fs::read_dir(dir.path())?.count(), 0);
+
+        let mut writer = get_writer(output, true)?;
+        writer.write_all(b"done")?;
+        writer.commit()?;
+        assert_eq!(fs::read_to_string(&path)?, "done");
+
+        assert!(get_writer(output, false).is_err());
+        let mut writer = get_writer(output, true)?;
+        writer.write_all(b"again")?;
+        writer.commit()?;
+        assert_eq!(fs::read_to_string(&path)?, "again");
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_get_writer_file_mode() -> Result<(), Error> {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir()?;
+        let mode = |path: &Path| -> Result<u32, Error> {
+            Ok(fs::metadata(path)?.permissions().mode() & 0o777)
+        };
+        let plain = dir.path().join("plain.txt");
+        fs::write(&plain, "")?;
+        let path = dir.path().join("out.txt");
+        let output = path.to_str().unwrap();
+
+        get_writer(output, true)?.commit()?;
+        assert_eq!(mode(&path)?, mode(&plain)?);
+
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o640))?;
+        get_writer(output, true)?.commit()?;
+        assert_eq!(mode(&path)?, 0o640);
+        Ok(())
+    }
+}